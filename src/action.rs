@@ -1,3 +1,4 @@
+use crate::alliance::AllianceOptions;
 use crate::blocks::action::UnitCommand;
 use crate::blocks::command::{GameComponent, ParsedAction, Position, SelectedComponent};
 use crate::building::{Building, Upgrade};
@@ -51,10 +52,7 @@ pub(crate) fn from_parsed_action(
                             on: None,
                         }),
                         UnitCommand::Stop | UnitCommand::Hold => None,
-                        _ => {
-                            println!("TODO (action): {:?}", ability);
-                            None
-                        }
+                        _ => None, // TODO: other unit commands
                     },
                     _ => None,
                 }
@@ -66,7 +64,7 @@ pub(crate) fn from_parsed_action(
                     let target = components
                         .get(&ability.object_1)
                         .or_else(|| components.get(&ability.object_2))
-                        .map(GameComponent::clone);
+                        .cloned();
                     Some(Action::UsedSpell {
                         spell: spell.clone(),
                         target,
@@ -97,7 +95,7 @@ pub(crate) fn from_parsed_action(
                         target: components
                             .get(&ability.object_1)
                             .or_else(|| components.get(&ability.object_2))
-                            .map(GameComponent::clone),
+                            .cloned(),
                     }),
                     UnitCommand::UseItem(slot) => Some(Action::UseItem {
                         item_or_slot: ItemOrSlot::Slot(*slot),
@@ -105,7 +103,7 @@ pub(crate) fn from_parsed_action(
                         on: components
                             .get(&ability.object_1)
                             .or_else(|| components.get(&ability.object_2))
-                            .map(GameComponent::clone),
+                            .cloned(),
                     }),
                     UnitCommand::SwapItem(_) => None,
                     UnitCommand::ChangeShopBuyer => {
@@ -118,21 +116,9 @@ pub(crate) fn from_parsed_action(
                             None
                         }
                     }
-                    _ => {
-                        println!(
-                            "TODO(targetpositionobjectid)2 ability: {:?}, selection: {:?}",
-                            ability, selection
-                        );
-                        None
-                    }
+                    _ => None, // TODO: other unit commands on a target
                 },
-                _ => {
-                    println!(
-                        "TODO(targetpositionobjectid)3 ability: {:?}, selection: {:?}",
-                        ability, selection
-                    );
-                    None
-                }
+                _ => None, // TODO: other items on a target
             }
         }
         ParsedAction::UnitBuildingAbilityTwoTargetPositions(ability) => match &ability.item_2 {
@@ -158,24 +144,12 @@ pub(crate) fn from_parsed_action(
                 target: None,
                 position: Some(ability.target_position.clone()),
             }),
-            GameComponent::Action(action) => match action {
-                UnitCommand::UseItem(slot) => Some(Action::UseItem {
-                    item_or_slot: ItemOrSlot::Slot(*slot),
-                    at: Some(ability.target_position.clone()),
-                    on: None,
-                }),
-                _ => {
-                    println!(
-                        "TODO(UnitBuildingAbilityTargetPosition + Command) {:?}",
-                        ability
-                    );
-                    None
-                }
-            },
-            _ => {
-                println!("TODO(UnitBuildingAbilityTargetPosition) {:?}", ability);
-                None
-            }
+            GameComponent::Action(UnitCommand::UseItem(slot)) => Some(Action::UseItem {
+                item_or_slot: ItemOrSlot::Slot(*slot),
+                at: Some(ability.target_position.clone()),
+                on: None,
+            }),
+            _ => None, // TODO: other unit commands and items at a position
         },
         ParsedAction::GiveItem(action) => {
             if let Some(GameComponent::Item(item)) = components
@@ -191,15 +165,38 @@ pub(crate) fn from_parsed_action(
         | ParsedAction::AssignGroupHotkey(_)
         | ParsedAction::PreSubselection
         | ParsedAction::SelectSubgroup(_)
+        | ParsedAction::SelectSubgroupIndex(_)
         | ParsedAction::EnterBuildingSubmenu
         | ParsedAction::ChooseHeroSkillSubmenu
         | ParsedAction::Data(_)
         | ParsedAction::EscapedPressed
-        | ParsedAction::SelectGroupHotkey(_) => None,
-        _ => {
-            println!("Unhandled parsed action: {:?}", action);
-            None
-        } // TODO
+        | ParsedAction::SelectGroupHotkey(_)
+        | ParsedAction::TransferResources(_)
+        | ParsedAction::ChangeAllyOptions(_)
+        | ParsedAction::MapTriggerChat(_)
+        | ParsedAction::Pause
+        | ParsedAction::Resume
+        | ParsedAction::SetSpeed(_)
+        | ParsedAction::IncreaseGameSpeed
+        | ParsedAction::DecreaseGameSpeed
+        | ParsedAction::Save(_)
+        | ParsedAction::SaveFinished
+        | ParsedAction::ScenarioTrigger(_)
+        | ParsedAction::SelectUnit(_)
+        | ParsedAction::Opaque { .. }
+        | ParsedAction::Cheat(_)
+        | ParsedAction::ArrowKey(_)
+        | ParsedAction::Mouse(_)
+        | ParsedAction::W3Api(_)
+        | ParsedAction::SyncData(_)
+        | ParsedAction::SelectGroundItem(_)
+        | ParsedAction::CancelHeroRevival(_)
+        | ParsedAction::RemoveUnitFromBuildingQueue(_)
+        | ParsedAction::MinimapSignal(_)
+        | ParsedAction::ContinueGame
+        | ParsedAction::W3MMD(_)
+        | ParsedAction::Unknown(_)
+        | ParsedAction::Invalid(_) => None,
     }
 }
 
@@ -214,7 +211,7 @@ fn units_from_selection(selection: &[SelectedComponent]) -> Vec<Unit> {
 }
 
 fn first_unit_from_selection(selection: &[SelectedComponent]) -> Option<&GameComponent> {
-    selection.get(0).and_then(|s| s.kind.as_ref())
+    selection.first().and_then(|s| s.kind.as_ref())
 }
 
 fn building_selected(selection: &[SelectedComponent]) -> bool {
@@ -269,5 +266,51 @@ pub enum Action {
         at: Option<Position>,
         target: Option<GameComponent>,
     },
+    /// Gold and lumber sent to an ally
+    TransferResources {
+        to_player: u8,
+        gold: u32,
+        lumber: u32,
+    },
+    /// The alliance options granted to another player changed
+    ChangeAlliance {
+        with_player: u8,
+        options: AllianceOptions,
+    },
+    #[deprecated(note = "never emitted, players leaving are `Event::PlayerLeft` events")]
     Leave,
 }
+
+/// Flags of ability actions (orders), telling how the order was given
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct AbilityFlags {
+    /// Shift was held: the order is queued after the current ones
+    pub queued: bool,
+    /// Applies to every unit of the active subgroup
+    pub subgroup: bool,
+    pub area_effect: bool,
+    /// Applies to every selected unit
+    pub group: bool,
+    /// The group moves without keeping its formation
+    pub no_formation: bool,
+    /// Given with a command card button or its hotkey, instead of a right click
+    pub command_card: bool,
+    /// Toggles autocast, with a right click on the ability's button
+    pub autocast: bool,
+    pub raw: u16,
+}
+
+impl From<u16> for AbilityFlags {
+    fn from(raw: u16) -> Self {
+        AbilityFlags {
+            queued: raw & 0x0001 != 0,
+            subgroup: raw & 0x0002 != 0,
+            area_effect: raw & 0x0004 != 0,
+            group: raw & 0x0008 != 0,
+            no_formation: raw & 0x0010 != 0,
+            command_card: raw & 0x0040 != 0,
+            autocast: raw & 0x0100 != 0,
+            raw,
+        }
+    }
+}
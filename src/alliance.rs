@@ -0,0 +1,207 @@
+//! Who allied with whom, and when
+use crate::action::Action;
+use crate::event::Event;
+use crate::game::Game;
+use crate::player::Player;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Allied, with shared vision and allied victory
+const TEAMMATES: u32 = 0x43F;
+const SHARED_CONTROL: u32 = 0x40;
+
+/// Alliance options a player grants to another one, from the "Allies" menu
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct AllianceOptions {
+    pub allied: bool,
+    pub shared_vision: bool,
+    pub shared_control: bool,
+    pub shared_advanced_control: bool,
+    pub allied_victory: bool,
+    pub raw: u32,
+}
+
+impl From<u32> for AllianceOptions {
+    fn from(raw: u32) -> Self {
+        AllianceOptions {
+            // passive, help request / response, shared experience and spells
+            allied: raw & 0x1F == 0x1F,
+            shared_vision: raw & 0x20 != 0,
+            shared_control: raw & 0x40 != 0,
+            shared_advanced_control: raw & 0x80 != 0,
+            allied_victory: raw & 0x400 != 0,
+            raw,
+        }
+    }
+}
+
+/// An alliance is one-sided: `player_id` grants `options` to `with_player`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllianceChange {
+    pub time: Duration,
+    pub player_id: u8,
+    pub with_player: u8,
+    pub options: AllianceOptions,
+}
+
+/// Alliance options over time, for every pair of players
+#[derive(Debug, Default)]
+pub struct AllianceTimeline {
+    /// Lobby teammates start allied
+    starting: BTreeMap<(u8, u8), AllianceOptions>,
+    changes: BTreeMap<(u8, u8), Vec<AllianceChange>>,
+}
+
+impl AllianceTimeline {
+    /// Every change `player_id` made towards `with_player`, in order
+    pub fn between(&self, player_id: u8, with_player: u8) -> &[AllianceChange] {
+        self.changes
+            .get(&(player_id, with_player))
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+    }
+
+    /// What `player_id` grants to `with_player` at `time` (game time): the lobby's options
+    /// until they change them, `None` for players of different teams who haven't yet
+    pub fn options_at(
+        &self,
+        player_id: u8,
+        with_player: u8,
+        time: Duration,
+    ) -> Option<AllianceOptions> {
+        self.between(player_id, with_player)
+            .iter()
+            .take_while(|change| change.time <= time)
+            .last()
+            .map(|change| change.options)
+            .or_else(|| self.starting.get(&(player_id, with_player)).copied())
+    }
+
+    /// Both players are allied with each other at `time`
+    pub fn allied_at(&self, player_1: u8, player_2: u8, time: Duration) -> bool {
+        let allied = |from, to| self.options_at(from, to, time).is_some_and(|o| o.allied);
+        allied(player_1, player_2) && allied(player_2, player_1)
+    }
+
+    /// Every change, in order
+    pub fn changes(&self) -> Vec<&AllianceChange> {
+        let mut changes: Vec<&AllianceChange> = self.changes.values().flatten().collect();
+        changes.sort_by_key(|change| change.time);
+        changes
+    }
+}
+
+impl Game {
+    pub fn alliances(&self) -> AllianceTimeline {
+        let mut timeline = AllianceTimeline::default();
+        let mut starting = TEAMMATES;
+        if self.settings.full_shared_unit_control {
+            starting |= SHARED_CONTROL;
+        }
+        // computers all have the player id 0, they are left out
+        let players: Vec<&Player> = self
+            .players
+            .iter()
+            .filter(|p| !p.is_computer() && !p.is_observer())
+            .collect();
+        for player in &players {
+            for teammate in players
+                .iter()
+                .filter(|p| p.id != player.id && p.team_id == player.team_id)
+            {
+                timeline
+                    .starting
+                    .insert((player.id, teammate.id), AllianceOptions::from(starting));
+            }
+        }
+        for event in self.events() {
+            if let (
+                Some(player_id),
+                Event::Action {
+                    action:
+                        Action::ChangeAlliance {
+                            with_player,
+                            options,
+                        },
+                    ..
+                },
+            ) = (event.player_id, event.event)
+            {
+                if with_player == 0 {
+                    continue; // a computer
+                }
+                timeline
+                    .changes
+                    .entry((player_id, with_player))
+                    .or_default()
+                    .push(AllianceChange {
+                        time: event.time,
+                        player_id,
+                        with_player,
+                        options,
+                    });
+            }
+        }
+        timeline
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alliance::AllianceOptions;
+    use crate::game::Game;
+    use crate::tests::{replay_bytes, with_computers};
+    use std::time::Duration;
+
+    #[test]
+    fn decode_options() {
+        let options = AllianceOptions::from(0x047F);
+        assert!(options.allied && options.shared_vision && options.shared_control);
+        assert!(options.allied_victory);
+        assert!(!options.shared_advanced_control);
+        assert!(!AllianceOptions::from(0x1E).allied);
+    }
+
+    #[test]
+    fn alliance_timeline() {
+        let game = Game::from_bytes(&replay_bytes("reforged2010.w3g")).unwrap();
+        let timeline = game.alliances();
+        // slots 0 to 2 are players 3 to 5, who granted shared control to each other
+        let changes = timeline.changes();
+        assert_eq!(7, changes.len());
+        assert_eq!((5, 3), (changes[0].player_id, changes[0].with_player));
+        assert_eq!(Duration::from_millis(9560), changes[0].time);
+        // lobby teammates are allied from the start, without shared control
+        let before = Duration::from_secs(12);
+        let options = timeline.options_at(3, 4, before).unwrap();
+        assert!(options.allied && options.shared_vision && !options.shared_control);
+        assert!(timeline.allied_at(3, 4, before));
+        assert!(
+            timeline
+                .options_at(3, 4, game.header.duration)
+                .unwrap()
+                .shared_control
+        );
+        assert_eq!(None, timeline.options_at(3, 2, before));
+        let end = game.header.duration;
+        for (player_1, player_2) in [(3, 4), (3, 5), (4, 5), (6, 7), (2, 7)] {
+            assert!(timeline.allied_at(player_1, player_2, end));
+        }
+        assert!(!timeline.allied_at(3, 2, end));
+        // observers aren't anybody's teammate
+        assert!(!timeline.allied_at(3, 1, end));
+        // one-sided
+        assert!(timeline.options_at(7, 6, end).unwrap().shared_control);
+        assert!(!timeline.options_at(6, 7, end).unwrap().shared_control);
+    }
+
+    #[test]
+    fn computers_left_out() {
+        // computer teammates for player 1
+        let game = with_computers(0);
+        assert!(game.players.iter().any(|p| p.is_computer()));
+        let timeline = game.alliances();
+        assert_eq!(None, timeline.options_at(1, 0, Duration::ZERO));
+        assert_eq!(None, timeline.options_at(0, 1, Duration::ZERO));
+    }
+}
@@ -0,0 +1,174 @@
+//! Strips personal information from a replay before sharing it
+use crate::blocks::chat::{Addressee, ChatMsgBlock};
+use crate::blocks::gameblock::GameBlock;
+use crate::game::Game;
+use crate::map::split_map_creator;
+use crate::utils::{decode, encode, write_zero_terminated};
+
+/// What to do with chat messages which are not sent to everyone (allies, observers, direct)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PrivateChat {
+    /// Remove the messages from the replay
+    Drop,
+    /// Keep the messages, replacing their text with `*`
+    Mask,
+}
+
+fn anonymous_name(player_id: u8) -> String {
+    format!("Player{}", player_id)
+}
+
+impl Game {
+    /// Renames players after their ids (`Player1`, `Player2`, ...) everywhere they're mentioned,
+    /// clears clans and the private string, drops or masks non-public chat messages.
+    /// `write` or `to_bytes` give the anonymized replay back
+    pub fn anonymize(&mut self, private_chat: PrivateChat) {
+        // (original name, anonymous name), longest first so that nicknames don't clobber battle tags
+        let mut renamed: Vec<(String, String)> = std::iter::once(&self.metadata.host)
+            .chain(&self.players_metadata)
+            .map(|m| (m.name(), anonymous_name(m.id)))
+            .chain(
+                self.players
+                    .iter()
+                    .filter(|p| !p.is_computer())
+                    .map(|p| (p.name.clone(), anonymous_name(p.id))),
+            )
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        let nicknames: Vec<(String, String)> = renamed
+            .iter()
+            .filter_map(|(name, anonymous)| {
+                name.split_once('#')
+                    .filter(|(nickname, _)| nickname.chars().count() >= 3)
+                    .map(|(nickname, _)| (nickname.to_string(), anonymous.clone()))
+            })
+            .collect();
+        renamed.extend(nicknames);
+        renamed.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+        renamed.dedup_by(|a, b| a.0 == b.0);
+
+        self.rename_map_creator(&renamed);
+        for meta in std::iter::once(&mut self.metadata.host).chain(self.players_metadata.iter_mut())
+        {
+            if !meta.name.is_empty() {
+                meta.name = anonymous_name(meta.id).into_bytes();
+            }
+        }
+        for record in self.reforged_records.iter_mut() {
+            record.rename_players(&anonymous_name);
+        }
+        for player in self.players.iter_mut().filter(|p| !p.is_computer()) {
+            player.name = anonymous_name(player.id);
+            player.clan = None;
+        }
+        self.metadata.private_string.clear();
+
+        self.blocks.retain_mut(|block| match block {
+            GameBlock::PlayerChatMsg(msg) => match msg.kind {
+                ChatMsgBlock::Msg(mode) if Addressee::from_mode(mode) == Addressee::All => {
+                    for (name, anonymous) in &renamed {
+                        msg.text = msg.text.replace(name.as_str(), anonymous);
+                    }
+                    true
+                }
+                _ => match private_chat {
+                    PrivateChat::Drop => false,
+                    PrivateChat::Mask => {
+                        msg.text = "*".repeat(msg.text.chars().count());
+                        true
+                    }
+                },
+            },
+            _ => true,
+        });
+    }
+
+    /// Custom games store the battle tag of their creator in the map info
+    fn rename_map_creator(&mut self, renamed: &[(String, String)]) {
+        let map_info = decode(&self.metadata.encoded_map_info);
+        if let Ok((rest, (head, creator))) = split_map_creator(&map_info) {
+            let creator = String::from_utf8_lossy(creator);
+            if let Some((_, anonymous)) = renamed.iter().find(|(name, _)| *name == creator) {
+                let mut decoded = head.to_vec();
+                write_zero_terminated(&mut decoded, anonymous.as_bytes());
+                decoded.extend_from_slice(rest);
+                self.metadata.encoded_map_info = encode(&decoded);
+                self.map.creator = anonymous.clone();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::anonymize::PrivateChat;
+    use crate::blocks::chat::Addressee;
+    use crate::event::Event;
+    use crate::game::tests::decompressed;
+    use crate::game::Game;
+    use crate::tests::replay_bytes;
+
+    fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+        haystack.windows(needle.len()).any(|w| w == needle)
+    }
+
+    fn chat(game: &Game) -> Vec<(Addressee, String)> {
+        game.events()
+            .into_iter()
+            .filter_map(|e| match e.event {
+                Event::ChatMsg { addressee, message } => Some((addressee, message)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn anonymize_private_game() {
+        let bytes = replay_bytes("reforged_hunter2_privatestring.w3g");
+        let original = Game::from_bytes(&bytes).unwrap();
+        let mut game = Game::from_bytes(&bytes).unwrap();
+        game.anonymize(PrivateChat::Drop);
+        let written = game.to_bytes().unwrap();
+        let (_, data) = decompressed(&written);
+        for secret in ["hunter2", "pischner", "Wartoni"] {
+            assert!(!contains(&data, secret.as_bytes()), "{} leaked", secret);
+        }
+        let anonymized = Game::from_bytes(&written).unwrap();
+        assert_eq!(
+            vec!["Player1", "Player2"],
+            anonymized
+                .players
+                .iter()
+                .map(|p| p.name.as_str())
+                .collect::<Vec<&str>>()
+        );
+        assert_eq!(original.map.name, anonymized.map.name);
+        assert!(chat(&anonymized)
+            .iter()
+            .all(|(addressee, _)| *addressee == Addressee::All));
+        let actions = |g: &Game| {
+            g.events()
+                .into_iter()
+                .filter(|e| matches!(e.event, Event::Action { .. }))
+                .count()
+        };
+        assert_eq!(actions(&original), actions(&anonymized));
+    }
+
+    #[test]
+    fn mask_private_chat() {
+        let bytes = replay_bytes("reforged2010.w3g");
+        let original = Game::from_bytes(&bytes).unwrap();
+        let mut game = Game::from_bytes(&bytes).unwrap();
+        game.anonymize(PrivateChat::Mask);
+        let anonymized = Game::from_bytes(&game.to_bytes().unwrap()).unwrap();
+        let (before, after) = (chat(&original), chat(&anonymized));
+        assert_eq!(before.len(), after.len());
+        assert!(before.iter().any(|(a, _)| *a == Addressee::Allies));
+        for ((addressee, message), (_, masked)) in before.iter().zip(&after) {
+            if *addressee != Addressee::All {
+                assert_eq!("*".repeat(message.chars().count()), *masked);
+            }
+        }
+    }
+}
@@ -1,8 +1,7 @@
 use crate::blocks::gameblock::GameBlock;
-use crate::utils::zero_terminated_string;
-use nom::bytes::complete::take;
+use crate::utils::{write_zero_terminated, zero_terminated_string};
 use nom::{
-    number::complete::{le_u16, le_u8},
+    number::complete::{le_u16, le_u32, le_u8},
     IResult,
 };
 
@@ -22,8 +21,9 @@ pub(crate) struct PlayerChatMsgBlock {
 #[derive(Debug)]
 pub(crate) enum ChatMsgBlock {
     StartupMsg,
-    Msg(Addressee),
-    Unknown,
+    /// Raw chat mode, see `Addressee::from_mode`
+    Msg(u32),
+    Unknown(u8),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -35,12 +35,12 @@ pub enum Addressee {
 }
 
 impl Addressee {
-    fn from_u8(to: &[u8]) -> Addressee {
-        match to[0..2] {
-            [0, 0] => Addressee::All,
-            [0, 1] => Addressee::Allies,
-            [0, 2] => Addressee::Observers,
-            _ => Addressee::Direct,
+    pub(crate) fn from_mode(mode: u32) -> Addressee {
+        match mode {
+            0 => Addressee::All,
+            1 => Addressee::Allies,
+            2 => Addressee::Observers,
+            _ => Addressee::Direct, // 3 + player slot
         }
     }
 }
@@ -50,13 +50,13 @@ fn parse_message(input: &[u8]) -> IResult<&[u8], ChatMsgBlock> {
     match msg_type {
         16 => Ok((rest, ChatMsgBlock::StartupMsg)),
         32 => addressee_chat_msg(rest),
-        _ => Ok((rest, ChatMsgBlock::Unknown)),
+        _ => Ok((rest, ChatMsgBlock::Unknown(msg_type))),
     }
 }
 
 fn addressee_chat_msg(input: &[u8]) -> IResult<&[u8], ChatMsgBlock> {
-    let (rest, to) = take(4usize)(input)?;
-    Ok((rest, ChatMsgBlock::Msg(Addressee::from_u8(&to))))
+    let (rest, mode) = le_u32(input)?;
+    Ok((rest, ChatMsgBlock::Msg(mode)))
 }
 
 fn parse_msg_metadata(input: &[u8]) -> IResult<&[u8], PlayerChatMsgMetadata> {
@@ -82,3 +82,19 @@ pub(crate) fn player_chat_msg(input: &[u8]) -> IResult<&[u8], GameBlock> {
     });
     Ok((rest, block))
 }
+
+pub(crate) fn write_player_chat_msg(out: &mut Vec<u8>, msg: &PlayerChatMsgBlock) {
+    let mut body = Vec::with_capacity(msg.text.len() + 6);
+    match msg.kind {
+        ChatMsgBlock::StartupMsg => body.push(16),
+        ChatMsgBlock::Msg(mode) => {
+            body.push(32);
+            body.extend_from_slice(&mode.to_le_bytes());
+        }
+        ChatMsgBlock::Unknown(msg_type) => body.push(msg_type),
+    }
+    write_zero_terminated(&mut body, msg.text.as_bytes());
+    out.push(msg.player_id);
+    out.extend_from_slice(&(body.len() as u16).to_le_bytes());
+    out.extend_from_slice(&body);
+}
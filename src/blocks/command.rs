@@ -1,13 +1,18 @@
+use crate::action::AbilityFlags;
+use crate::alliance::AllianceOptions;
 use crate::blocks::action::UnitCommand;
 use crate::building::{Building, Upgrade};
 use crate::environment::Environment;
 use crate::item::Item;
+use crate::metadata::replay::GameVersion;
 use crate::spell::{HeroSpell, Spell, UnitSpell};
 use crate::unit::{Hero, Unit};
 use crate::utils::zero_terminated_string;
 use nom::bytes::complete::take;
-use nom::combinator::map_res;
-use nom::multi::{count, many0};
+use nom::combinator::{cond, map};
+use nom::error::{Error, ErrorKind};
+use nom::multi::count;
+use nom::sequence::pair;
 use nom::{
     number::complete::{le_f32, le_u16, le_u32, le_u8},
     IResult,
@@ -18,10 +23,20 @@ use std::convert::TryInto;
 #[derive(Debug, PartialEq)]
 pub struct CommandData {
     pub player: u8,
-    length: u16,
+    /// Raw actions, kept as-is to be written back
+    pub(crate) data: Vec<u8>,
     pub(crate) actions: Vec<ParsedAction>,
 }
 
+impl CommandData {
+    /// The first action that couldn't be decoded, the rest of the command was skipped
+    pub(crate) fn failed_action(&self) -> Option<&ParsedAction> {
+        self.actions
+            .iter()
+            .find(|action| matches!(action, ParsedAction::Unknown(_) | ParsedAction::Invalid(_)))
+    }
+}
+
 #[derive(Debug, PartialEq)]
 pub(crate) enum ParsedAction {
     Pause,
@@ -42,22 +57,46 @@ pub(crate) enum ParsedAction {
     AssignGroupHotkey(AssignGroupHotkeyAction),
     SelectGroupHotkey(u8),
     SelectSubgroup(SelectSubgroupAction),
+    /// Before 1.14b
+    SelectSubgroupIndex(u8),
     PreSubselection,
-    Unknown, // 1B, skipped
+    /// Follows subgroup changes, with the unit getting the focus
+    SelectUnit(UnitSelection),
+    /// Known layout, unknown meaning: 0x21 (two dwords) and 0x7A (five dwords, Reforged).
+    /// Deliberately kept as raw bytes, they are too rare in replays to be reverse-engineered
+    Opaque {
+        kind: u8,
+        data: Vec<u8>,
+    },
+    Cheat(Cheat),
     SelectGroundItem(SelectGroundItemAction),
     CancelHeroRevival(CancelHeroRevivalAction),
     RemoveUnitFromBuildingQueue(RemoveUnitFromBuildingQueueAction),
     ChangeAllyOptions(ChangeAllyOptionsAction),
     TransferResources(TransferResourcesAction),
-    MapTriggerChat(String),
+    /// Chat received by the map's triggers, once per trigger listening to chat
+    MapTriggerChat(MapTriggerChatAction),
     EscapedPressed,
-    ScenarioTrigger,
+    ScenarioTrigger(ScenarioTriggerAction),
     ChooseHeroSkillSubmenu,
     EnterBuildingSubmenu,
     MinimapSignal(Position),
     ContinueGame,
     W3MMD(W3MMDAction),
+    /// Reforged: camera moves with the arrow keys
+    ArrowKey(ArrowKeyAction),
+    /// Reforged
+    Mouse(MouseAction),
+    /// Reforged: calls to the game's API
+    W3Api(W3ApiAction),
+    /// Reforged: `BlzSendSyncData` sent by map scripts, and anti-cheat checks
+    SyncData(SyncDataAction),
     Data([u8; 16]),
+    /// Unknown kind of action, the rest of the command is skipped
+    Unknown(u8),
+    /// Known kind of action, but its payload couldn't be decoded (most likely truncated).
+    /// The rest of the command is skipped
+    Invalid(u8),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -105,17 +144,38 @@ impl ParsedAction {
         }
     }
 
+    /// How the order was given, for ability actions
+    pub(crate) fn ability_flags(&self) -> Option<AbilityFlags> {
+        match self {
+            ParsedAction::UnitBuildingAbilityNoParams(ability) => Some(ability.flags),
+            ParsedAction::UnitBuildingAbilityTargetPosition(ability) => Some(ability.flags),
+            ParsedAction::UnitBuildingAbilityTargetPositionTargetObjectId(ability) => {
+                Some(ability.flags)
+            }
+            ParsedAction::GiveItem(action) => Some(action.flags),
+            ParsedAction::UnitBuildingAbilityTwoTargetPositions(ability) => Some(ability.flags),
+            _ => None,
+        }
+    }
+
     fn discard(&self) -> bool {
         matches!(
             self,
             ParsedAction::W3MMD(_)
                 | ParsedAction::ContinueGame
                 | ParsedAction::EscapedPressed
-                | ParsedAction::ScenarioTrigger
+                | ParsedAction::ScenarioTrigger(_)
                 | ParsedAction::MapTriggerChat(_)
                 | ParsedAction::SaveFinished
                 | ParsedAction::PreSubselection
-                | ParsedAction::Unknown
+                | ParsedAction::SelectUnit(_)
+                | ParsedAction::Opaque { .. }
+                | ParsedAction::ArrowKey(_)
+                | ParsedAction::Mouse(_)
+                | ParsedAction::W3Api(_)
+                | ParsedAction::SyncData(_)
+                | ParsedAction::Unknown(_)
+                | ParsedAction::Invalid(_)
         )
     }
 
@@ -162,36 +222,22 @@ pub struct Position {
     pub(crate) y: f32,
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Command {
-    /*
-    Train,
-    Buy,
-    TrainHero,
-    Order,
-    Summon,
-     */
-    /// TODO: Understand this, this must be something like "do", "buy", "queue", "use item", etc.
-    /// => try to understand by pattern matching replays, the doc isn't crystal clear (or it has changed)
-    Unknown(u16),
-}
-
 #[derive(Debug, PartialEq)]
 pub struct UnitBuildingAbilityActionNoParams {
-    command: Command,
+    pub(crate) flags: AbilityFlags,
     pub(crate) item: GameComponent,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct UnitBuildingAbilityActionTargetPosition {
-    pub(crate) command: Command,
+    pub(crate) flags: AbilityFlags,
     pub(crate) item: GameComponent,
     pub(crate) target_position: Position,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct UnitBuildingAbilityActionTargetPositionTargetObjectId {
-    command: Command,
+    pub(crate) flags: AbilityFlags,
     pub(crate) item: GameComponent,
     pub(crate) target_position: Position,
     pub(crate) object_1: u32,
@@ -200,7 +246,7 @@ pub struct UnitBuildingAbilityActionTargetPositionTargetObjectId {
 
 #[derive(Debug, PartialEq)]
 pub struct GiveItemToUnitAction {
-    command: Command,
+    pub(crate) flags: AbilityFlags,
     pub(crate) item: GameComponent,
     pub(crate) target_position: Position,
     pub(crate) object_1: u32,
@@ -211,7 +257,7 @@ pub struct GiveItemToUnitAction {
 
 #[derive(Debug, PartialEq)]
 pub struct UnitBuildingAbilityActionTwoTargetPositions {
-    command: Command,
+    pub(crate) flags: AbilityFlags,
     item_1: GameComponent,
     target_position_1: Position,
     pub(crate) item_2: GameComponent,
@@ -263,34 +309,122 @@ pub struct RemoveUnitFromBuildingQueueAction {
 
 #[derive(Debug, PartialEq)]
 pub struct ChangeAllyOptionsAction {
-    player_slot: u8,
-    option: [u8; 4],
+    pub(crate) player_slot: u8,
+    pub(crate) options: AllianceOptions,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct TransferResourcesAction {
-    player_slot: u8,
-    gold_amount: [u8; 4],   // TODO: real number
-    lumber_amount: [u8; 4], // TODO: real number
+    pub(crate) player_slot: u8,
+    pub(crate) gold: u32,
+    pub(crate) lumber: u32,
+}
+
+/// `trigger_1` and `trigger_2` are the object ids of the map trigger
+#[derive(Debug, PartialEq)]
+pub struct MapTriggerChatAction {
+    pub(crate) trigger_1: u32,
+    pub(crate) trigger_2: u32,
+    pub(crate) text: String,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ScenarioTriggerAction {
+    pub(crate) trigger_1: u32,
+    pub(crate) trigger_2: u32,
+    /// Looks like the number of times the trigger ran
+    pub(crate) counter: u32,
 }
 
 #[derive(Debug, PartialEq)]
 pub struct W3MMDAction {
     filename: String,
-    mission_key: String,
-    key: String,
+    pub(crate) mission_key: String,
+    pub(crate) key: String,
     value: u32,
 }
 
+/// Cheat codes, only available in single player games
+#[derive(Debug, PartialEq)]
+pub enum Cheat {
+    TheDudeAbides,
+    SomebodySetUpUsTheBomb,
+    WarpTen,
+    IocainePowder,
+    PointBreak,
+    WhosYourDaddy,
+    KeyserSoze(u32),
+    LeafitToMe(u32),
+    ThereIsNoSpoon,
+    StrengthAndHonor,
+    ItVexesMe,
+    WhoIsJohnGalt,
+    GreedIsGood(u32),
+    DayLightSavings(f32),
+    ISeeDeadPeople,
+    Synergy,
+    SharpAndShiny,
+    AllYourBaseAreBelongToUs,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ArrowKey {
+    Left,
+    Right,
+    Down,
+    Up,
+    Unknown(u8),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ArrowKeyAction {
+    key: ArrowKey,
+    pressed: bool,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MouseAction {
+    event: u8,
+    position: Position,
+    button: u8,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct W3ApiAction {
+    command: u32,
+    data: u32,
+    buffer: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SyncDataAction {
+    prefix: String,
+    data: String,
+    unknown: u32,
+}
+
 #[derive(Debug, PartialEq)]
 pub enum SelectionMode {
     Add,
     Remove,
 }
 
-fn parse_ability(input: &[u8]) -> IResult<&[u8], Command> {
-    let (rest, command) = le_u16(input)?;
-    Ok((rest, Command::Unknown(command)))
+fn parse_ability(input: &[u8], version: GameVersion) -> IResult<&[u8], AbilityFlags> {
+    let (rest, flags) = if version.has_short_ability_flags() {
+        map(le_u8, u16::from)(input)?
+    } else {
+        le_u16(input)?
+    };
+    Ok((rest, AbilityFlags::from(flags)))
+}
+
+/// Two unknown dwords following the item id of ability actions (since 1.07)
+fn ability_unknowns(input: &[u8], version: GameVersion) -> IResult<&[u8], ()> {
+    let (rest, _) = cond(
+        version.has_ability_unknowns(),
+        pair(le_u32, le_u32), // TODO
+    )(input)?;
+    Ok((rest, ()))
 }
 
 fn parse_selection_mode(input: &[u8]) -> IResult<&[u8], SelectionMode> {
@@ -301,24 +435,52 @@ fn parse_selection_mode(input: &[u8]) -> IResult<&[u8], SelectionMode> {
     }
 }
 
-fn parse_actions(input: &[u8]) -> IResult<&[u8], Vec<ParsedAction>> {
-    many0(parse_action)(input)
+/// The size of an action can't be guessed from its kind:
+/// parsing stops at the first one that can't be decoded, and the rest of the command is skipped
+fn parse_actions(input: &[u8], version: GameVersion) -> IResult<&[u8], Vec<ParsedAction>> {
+    let mut actions = Vec::new();
+    let mut rest = input;
+    while let Some(&kind) = rest.first() {
+        match parse_action(rest, version) {
+            Ok((next, action)) => {
+                actions.push(action);
+                rest = next;
+            }
+            // `parse_action` doesn't know the kind of action
+            Err(nom::Err::Error(e)) if e.code == ErrorKind::Switch && e.input == rest => {
+                actions.push(ParsedAction::Unknown(kind));
+                break;
+            }
+            Err(_) => {
+                actions.push(ParsedAction::Invalid(kind));
+                break;
+            }
+        }
+    }
+    Ok((rest, actions))
 }
 
-pub(crate) fn parse_command(input: &[u8]) -> IResult<&[u8], CommandData> {
+pub(crate) fn parse_command(input: &[u8], version: GameVersion) -> IResult<&[u8], CommandData> {
     let (rest, player) = le_u8(input)?;
     let (rest, length) = le_u16(rest)?;
-    let (rest, actions) = map_res(take(length as usize), parse_actions)(rest)?;
+    let (rest, data) = take(length as usize)(rest)?;
+    let (_, actions) = parse_actions(data, version)?;
     Ok((
         rest,
         CommandData {
             player,
-            length,
-            actions: actions.1,
+            data: data.to_vec(),
+            actions,
         },
     ))
 }
 
+pub(crate) fn write_command(out: &mut Vec<u8>, command: &CommandData) {
+    out.push(command.player);
+    out.extend_from_slice(&(command.data.len() as u16).to_le_bytes());
+    out.extend_from_slice(&command.data);
+}
+
 fn str_component(input: &str) -> GameComponent {
     Unit::from_str(input)
         .map(GameComponent::Unit) // most often first
@@ -337,7 +499,7 @@ fn binary_component(input: [u8; 2]) -> GameComponent {
         .or_else(|| UnitSpell::from_bin(input).map(Spell::Unit))
         .map(GameComponent::UsedSpell)
         .or_else(|| UnitCommand::from_bin(input).map(GameComponent::Action))
-        .unwrap_or_else(|| GameComponent::UnknownBin(input))
+        .unwrap_or(GameComponent::UnknownBin(input))
 }
 
 fn game_component(input: &[u8]) -> IResult<&[u8], GameComponent> {
@@ -358,8 +520,17 @@ fn game_component(input: &[u8]) -> IResult<&[u8], GameComponent> {
     }
 }
 
-pub(crate) fn parse_action(input: &[u8]) -> IResult<&[u8], ParsedAction> {
+pub(crate) fn parse_action(input: &[u8], version: GameVersion) -> IResult<&[u8], ParsedAction> {
     let (rest, kind) = le_u8(input)?;
+    let kind = if version.has_legacy_subgroup_actions() {
+        match kind {
+            25 => return legacy_select_subgroup(rest),
+            26..=30 => kind + 1,
+            _ => kind,
+        }
+    } else {
+        kind
+    };
     match kind {
         1 => Ok((rest, ParsedAction::Pause)),
         2 => Ok((rest, ParsedAction::Resume)),
@@ -368,26 +539,23 @@ pub(crate) fn parse_action(input: &[u8]) -> IResult<&[u8], ParsedAction> {
         5 => Ok((rest, ParsedAction::DecreaseGameSpeed)),
         6 => save_game(rest),
         7 => save_game_finished(rest),
-        16 => unit_building_ability_no_params(rest),
-        17 => unit_building_ability_target_position(rest),
-        18 => unit_building_ability_target_position_target_object_id(rest),
-        19 => give_item(rest),
-        20 => unit_building_ability_two_target_positions(rest),
+        16 => unit_building_ability_no_params(rest, version),
+        17 => unit_building_ability_target_position(rest, version),
+        18 => unit_building_ability_target_position_target_object_id(rest, version),
+        19 => give_item(rest, version),
+        20 => unit_building_ability_two_target_positions(rest, version),
         22 => change_selection(rest),
         23 => assign_group_hotkey(rest),
         24 => select_group_hotkey(rest),
         25 => select_subgroup(rest),
         26 => Ok((rest, ParsedAction::PreSubselection)),
-        27 => unknown_9(rest),
+        27 => select_unit(rest),
         28 => select_ground_item(rest),
         29 => cancel_hero_revival(rest),
         30 => remove_unit_from_building_queue(rest),
         31 => remove_unit_from_building_queue(rest),
-        33 => unknown_8(rest),
-        39 => unknown_5(rest),
-        40 => unknown_5(rest),
-        45 => unknown_5(rest),
-        46 => unknown_4(rest),
+        33 => opaque(rest, kind, 8),
+        32..=50 => cheat(rest, kind),
         80 => change_ally_options(rest),
         81 => transfer_resources(rest),
         96 => map_trigger_chat(rest),
@@ -400,13 +568,14 @@ pub(crate) fn parse_action(input: &[u8]) -> IResult<&[u8], ParsedAction> {
         105 => continue_game(rest),
         106 => continue_game(rest),
         107 => w3mmd(rest),
-        117 => unknown_1(rest),
-        119 => unknown_13(rest),
-        120 => unknown_20(rest),
+        117 => arrow_key(rest),
+        118 => mouse(rest),
+        119 => w3api(rest),
+        120 => sync_data(rest),
+        122 => opaque(rest, kind, 20),
         123 => data_action(rest),
-        _ => unknown(rest),
+        _ => Err(nom::Err::Error(Error::new(input, ErrorKind::Switch))),
     }
-    // TODO: get missing from there: https://gist.github.com/ForNeVeR/48dfcf05626abb70b35b8646dd0d6e92
 }
 
 fn parse_position(input: &[u8]) -> IResult<&[u8], Position> {
@@ -415,9 +584,15 @@ fn parse_position(input: &[u8]) -> IResult<&[u8], Position> {
     Ok((rest, Position { x, y }))
 }
 
-fn unknown_bytes(input: &[u8], len: usize) -> IResult<&[u8], ParsedAction> {
-    let (rest, _) = take(len)(input)?;
-    Ok((rest, ParsedAction::Unknown))
+fn opaque(input: &[u8], kind: u8, len: usize) -> IResult<&[u8], ParsedAction> {
+    let (rest, data) = take(len)(input)?;
+    Ok((
+        rest,
+        ParsedAction::Opaque {
+            kind,
+            data: data.to_vec(),
+        },
+    ))
 }
 
 fn set_speed_game(input: &[u8]) -> IResult<&[u8], ParsedAction> {
@@ -435,30 +610,34 @@ fn save_game_finished(input: &[u8]) -> IResult<&[u8], ParsedAction> {
     Ok((rest, ParsedAction::SaveFinished))
 }
 
-fn unit_building_ability_no_params(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    let (rest, ability) = parse_ability(input)?;
+fn unit_building_ability_no_params(
+    input: &[u8],
+    version: GameVersion,
+) -> IResult<&[u8], ParsedAction> {
+    let (rest, flags) = parse_ability(input, version)?;
     let (rest, item) = game_component(rest)?;
-    let (rest, _) = le_u32(rest)?;
-    let (rest, _) = le_u32(rest)?;
+    let (rest, _) = ability_unknowns(rest, version)?;
     Ok((
         rest,
         ParsedAction::UnitBuildingAbilityNoParams(UnitBuildingAbilityActionNoParams {
-            command: ability,
+            flags,
             item,
         }),
     ))
 }
 
-fn unit_building_ability_target_position(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    let (rest, ability) = parse_ability(input)?;
+fn unit_building_ability_target_position(
+    input: &[u8],
+    version: GameVersion,
+) -> IResult<&[u8], ParsedAction> {
+    let (rest, flags) = parse_ability(input, version)?;
     let (rest, item) = game_component(rest)?;
-    let (rest, _) = le_u32(rest)?; // TODO
-    let (rest, _) = le_u32(rest)?; // TODO
+    let (rest, _) = ability_unknowns(rest, version)?;
     let (rest, target_position) = parse_position(rest)?;
     Ok((
         rest,
         ParsedAction::UnitBuildingAbilityTargetPosition(UnitBuildingAbilityActionTargetPosition {
-            command: ability,
+            flags,
             item,
             target_position,
         }),
@@ -467,11 +646,11 @@ fn unit_building_ability_target_position(input: &[u8]) -> IResult<&[u8], ParsedA
 
 fn unit_building_ability_target_position_target_object_id(
     input: &[u8],
+    version: GameVersion,
 ) -> IResult<&[u8], ParsedAction> {
-    let (rest, ability) = parse_ability(input)?;
+    let (rest, flags) = parse_ability(input, version)?;
     let (rest, item) = game_component(rest)?;
-    let (rest, _) = le_u32(rest)?; // TODO
-    let (rest, _) = le_u32(rest)?; // TODO
+    let (rest, _) = ability_unknowns(rest, version)?;
     let (rest, target_position) = parse_position(rest)?;
     let (rest, object_1) = le_u32(rest)?;
     let (rest, object_2) = le_u32(rest)?;
@@ -479,7 +658,7 @@ fn unit_building_ability_target_position_target_object_id(
         rest,
         ParsedAction::UnitBuildingAbilityTargetPositionTargetObjectId(
             UnitBuildingAbilityActionTargetPositionTargetObjectId {
-                command: ability,
+                flags,
                 item,
                 target_position,
                 object_1,
@@ -489,11 +668,10 @@ fn unit_building_ability_target_position_target_object_id(
     ))
 }
 
-fn give_item(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    let (rest, ability) = parse_ability(input)?;
+fn give_item(input: &[u8], version: GameVersion) -> IResult<&[u8], ParsedAction> {
+    let (rest, flags) = parse_ability(input, version)?;
     let (rest, item) = game_component(rest)?;
-    let (rest, _) = le_u32(rest)?; // TODO
-    let (rest, _) = le_u32(rest)?; // TODO
+    let (rest, _) = ability_unknowns(rest, version)?;
     let (rest, target_position) = parse_position(rest)?;
     let (rest, object_1) = le_u32(rest)?;
     let (rest, object_2) = le_u32(rest)?;
@@ -502,7 +680,7 @@ fn give_item(input: &[u8]) -> IResult<&[u8], ParsedAction> {
     Ok((
         rest,
         ParsedAction::GiveItem(GiveItemToUnitAction {
-            command: ability,
+            flags,
             item,
             target_position,
             object_1,
@@ -513,11 +691,13 @@ fn give_item(input: &[u8]) -> IResult<&[u8], ParsedAction> {
     ))
 }
 
-fn unit_building_ability_two_target_positions(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    let (rest, ability) = parse_ability(input)?;
+fn unit_building_ability_two_target_positions(
+    input: &[u8],
+    version: GameVersion,
+) -> IResult<&[u8], ParsedAction> {
+    let (rest, flags) = parse_ability(input, version)?;
     let (rest, item_1) = game_component(rest)?;
-    let (rest, _) = le_u32(rest)?; // TODO
-    let (rest, _) = le_u32(rest)?; // TODO
+    let (rest, _) = ability_unknowns(rest, version)?;
     let (rest, target_position_1) = parse_position(rest)?;
     let (rest, item_2) = game_component(rest)?;
     let (rest, _) = take(9usize)(rest)?; // TODO?
@@ -526,7 +706,7 @@ fn unit_building_ability_two_target_positions(input: &[u8]) -> IResult<&[u8], Pa
         rest,
         ParsedAction::UnitBuildingAbilityTwoTargetPositions(
             UnitBuildingAbilityActionTwoTargetPositions {
-                command: ability,
+                flags,
                 item_1,
                 target_position_1,
                 item_2,
@@ -588,6 +768,46 @@ fn select_subgroup(input: &[u8]) -> IResult<&[u8], ParsedAction> {
     ))
 }
 
+fn legacy_select_subgroup(input: &[u8]) -> IResult<&[u8], ParsedAction> {
+    let (rest, subgroup) = le_u8(input)?;
+    Ok((rest, ParsedAction::SelectSubgroupIndex(subgroup)))
+}
+
+fn select_unit(input: &[u8]) -> IResult<&[u8], ParsedAction> {
+    let (rest, _) = le_u8(input)?; // always 1
+    let (rest, unit) = unit_selection(rest)?;
+    Ok((rest, ParsedAction::SelectUnit(unit)))
+}
+
+fn cheat(input: &[u8], kind: u8) -> IResult<&[u8], ParsedAction> {
+    let amount = |input| {
+        let (rest, _) = le_u8(input)?;
+        le_u32(rest)
+    };
+    let (rest, cheat) = match kind {
+        32 => (input, Cheat::TheDudeAbides),
+        34 => (input, Cheat::SomebodySetUpUsTheBomb),
+        35 => (input, Cheat::WarpTen),
+        36 => (input, Cheat::IocainePowder),
+        37 => (input, Cheat::PointBreak),
+        38 => (input, Cheat::WhosYourDaddy),
+        39 => map(amount, Cheat::KeyserSoze)(input)?,
+        40 => map(amount, Cheat::LeafitToMe)(input)?,
+        41 => (input, Cheat::ThereIsNoSpoon),
+        42 => (input, Cheat::StrengthAndHonor),
+        43 => (input, Cheat::ItVexesMe),
+        44 => (input, Cheat::WhoIsJohnGalt),
+        45 => map(amount, Cheat::GreedIsGood)(input)?,
+        46 => map(le_f32, Cheat::DayLightSavings)(input)?,
+        47 => (input, Cheat::ISeeDeadPeople),
+        48 => (input, Cheat::Synergy),
+        49 => (input, Cheat::SharpAndShiny),
+        50 => (input, Cheat::AllYourBaseAreBelongToUs),
+        _ => return Err(nom::Err::Error(Error::new(input, ErrorKind::Switch))),
+    };
+    Ok((rest, ParsedAction::Cheat(cheat)))
+}
+
 fn select_ground_item(input: &[u8]) -> IResult<&[u8], ParsedAction> {
     let (rest, _) = take(1usize)(input)?;
     let (rest, object_1) = game_component(rest)?;
@@ -618,40 +838,56 @@ fn remove_unit_from_building_queue(input: &[u8]) -> IResult<&[u8], ParsedAction>
 
 fn change_ally_options(input: &[u8]) -> IResult<&[u8], ParsedAction> {
     let (rest, player_slot) = le_u8(input)?;
-    let (rest, option) = take(4usize)(rest)?;
+    let (rest, options) = le_u32(rest)?;
     Ok((
         rest,
         ParsedAction::ChangeAllyOptions(ChangeAllyOptionsAction {
             player_slot,
-            option: option[0..4].try_into().unwrap(),
+            options: AllianceOptions::from(options),
         }),
     ))
 }
 
 fn transfer_resources(input: &[u8]) -> IResult<&[u8], ParsedAction> {
     let (rest, player_slot) = le_u8(input)?;
-    let (rest, gold_amount) = take(4usize)(rest)?;
-    let (rest, lumber_amount) = take(4usize)(rest)?;
+    let (rest, gold) = le_u32(rest)?;
+    let (rest, lumber) = le_u32(rest)?;
     Ok((
         rest,
         ParsedAction::TransferResources(TransferResourcesAction {
             player_slot,
-            gold_amount: gold_amount[0..4].try_into().unwrap(),
-            lumber_amount: lumber_amount[0..4].try_into().unwrap(),
+            gold,
+            lumber,
         }),
     ))
 }
 
 fn map_trigger_chat(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    let (rest, _) = take(4usize)(input)?;
-    let (rest, _) = take(4usize)(rest)?;
-    let (rest, msg) = zero_terminated_string(rest)?;
-    Ok((rest, ParsedAction::MapTriggerChat(msg)))
+    let (rest, trigger_1) = le_u32(input)?;
+    let (rest, trigger_2) = le_u32(rest)?;
+    let (rest, text) = zero_terminated_string(rest)?;
+    Ok((
+        rest,
+        ParsedAction::MapTriggerChat(MapTriggerChatAction {
+            trigger_1,
+            trigger_2,
+            text,
+        }),
+    ))
 }
 
 fn scenario_trigger(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    let (rest, _) = take(12usize)(input)?;
-    Ok((rest, ParsedAction::ScenarioTrigger))
+    let (rest, trigger_1) = le_u32(input)?;
+    let (rest, trigger_2) = le_u32(rest)?;
+    let (rest, counter) = le_u32(rest)?;
+    Ok((
+        rest,
+        ParsedAction::ScenarioTrigger(ScenarioTriggerAction {
+            trigger_1,
+            trigger_2,
+            counter,
+        }),
+    ))
 }
 
 fn minimap_signal(input: &[u8]) -> IResult<&[u8], ParsedAction> {
@@ -686,34 +922,267 @@ fn data_action(input: &[u8]) -> IResult<&[u8], ParsedAction> {
     Ok((rest, ParsedAction::Data(data[0..16].try_into().unwrap())))
 }
 
-fn unknown(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    Ok((input, ParsedAction::Unknown))
+/// Even values press a key, odd ones release it
+fn arrow_key(input: &[u8]) -> IResult<&[u8], ParsedAction> {
+    let (rest, event) = le_u8(input)?;
+    let key = match event / 2 {
+        0 => ArrowKey::Left,
+        1 => ArrowKey::Right,
+        2 => ArrowKey::Down,
+        3 => ArrowKey::Up,
+        _ => ArrowKey::Unknown(event),
+    };
+    Ok((
+        rest,
+        ParsedAction::ArrowKey(ArrowKeyAction {
+            key,
+            pressed: event % 2 == 0,
+        }),
+    ))
 }
 
-fn unknown_1(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    unknown_bytes(input, 1)
+fn mouse(input: &[u8]) -> IResult<&[u8], ParsedAction> {
+    let (rest, event) = le_u8(input)?;
+    let (rest, position) = parse_position(rest)?;
+    let (rest, button) = le_u8(rest)?;
+    Ok((
+        rest,
+        ParsedAction::Mouse(MouseAction {
+            event,
+            position,
+            button,
+        }),
+    ))
 }
 
-fn unknown_4(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    unknown_bytes(input, 4)
+fn w3api(input: &[u8]) -> IResult<&[u8], ParsedAction> {
+    let (rest, command) = le_u32(input)?;
+    let (rest, data) = le_u32(rest)?;
+    let (rest, length) = le_u32(rest)?;
+    let (rest, buffer) = take(length as usize)(rest)?;
+    Ok((
+        rest,
+        ParsedAction::W3Api(W3ApiAction {
+            command,
+            data,
+            buffer: buffer.to_vec(),
+        }),
+    ))
 }
 
-fn unknown_5(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    unknown_bytes(input, 5)
+fn sync_data(input: &[u8]) -> IResult<&[u8], ParsedAction> {
+    let (rest, prefix) = zero_terminated_string(input)?;
+    let (rest, data) = zero_terminated_string(rest)?;
+    let (rest, unknown) = le_u32(rest)?;
+    Ok((
+        rest,
+        ParsedAction::SyncData(SyncDataAction {
+            prefix,
+            data,
+            unknown,
+        }),
+    ))
 }
 
-fn unknown_8(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    unknown_bytes(input, 8)
-}
+#[cfg(test)]
+mod tests {
+    use crate::action::AbilityFlags;
+    use crate::blocks::command::{
+        parse_actions, ArrowKey, ArrowKeyAction, Cheat, GameComponent, MapTriggerChatAction,
+        ParsedAction, ScenarioTriggerAction, SyncDataAction, UnitSelection,
+    };
+    use crate::metadata::replay::GameVersion;
+    use crate::unit::Unit;
 
-fn unknown_9(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    unknown_bytes(input, 9)
-}
+    fn version(patch: u32) -> GameVersion {
+        GameVersion { patch, build_no: 0 }
+    }
 
-fn unknown_13(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    unknown_bytes(input, 13)
-}
+    fn trained_unit(actions: &[ParsedAction]) -> Option<&GameComponent> {
+        match actions.first() {
+            Some(ParsedAction::UnitBuildingAbilityNoParams(ability)) => Some(&ability.item),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn ability_layout_depends_on_version() {
+        let reforged = [
+            [0x10, 0x00, 0x00].as_slice(),
+            b"aeph",
+            &[0xFF; 8],
+            &[0x01], // pause
+        ]
+        .concat();
+        let tft_112 = [[0x10, 0x00].as_slice(), b"aeph", &[0xFF; 8], &[0x01]].concat();
+        let roc_106 = [[0x10, 0x00].as_slice(), b"aeph", &[0x01]].concat();
+        for (bytes, patch) in [(reforged, 32), (tft_112, 12), (roc_106, 6)] {
+            let (rest, actions) = parse_actions(&bytes, version(patch)).unwrap();
+            assert!(rest.is_empty());
+            assert_eq!(2, actions.len(), "patch 1.{}", patch);
+            assert_eq!(
+                Some(&GameComponent::Unit(Unit::Peasant)),
+                trained_unit(&actions)
+            );
+            assert_eq!(ParsedAction::Pause, actions[1]);
+        }
+    }
 
-fn unknown_20(input: &[u8]) -> IResult<&[u8], ParsedAction> {
-    unknown_bytes(input, 13)
+    #[test]
+    fn ability_flags() {
+        // shift-queued right click, then a hotkey
+        let bytes = [
+            [0x11, 0x19, 0x00].as_slice(),
+            &[0x03, 0x00, 0x0D, 0x00],
+            &[0xFF; 8],
+            &[0x00; 8],
+            &[0x10, 0x42, 0x00],
+            b"aeph",
+            &[0xFF; 8],
+        ]
+        .concat();
+        let (rest, actions) = parse_actions(&bytes, version(32)).unwrap();
+        assert!(rest.is_empty());
+        let flags: Vec<AbilityFlags> = actions.iter().flat_map(|a| a.ability_flags()).collect();
+        assert_eq!(2, flags.len());
+        assert!(flags[0].queued && flags[0].group && flags[0].no_formation);
+        assert!(!flags[0].command_card);
+        assert!(flags[1].command_card && flags[1].subgroup);
+        assert!(!flags[1].queued);
+        assert_eq!(0x42, flags[1].raw);
+    }
+
+    #[test]
+    fn legacy_subgroup_actions() {
+        // select subgroup #2, then select ground item (0x1C since 1.14b)
+        let bytes = [
+            [0x19, 0x02, 0x1B, 0x04].as_slice(),
+            &[0x2A, 0x00, 0x00, 0x00],
+            &[0x2A, 0x00, 0x00, 0x00],
+        ]
+        .concat();
+        let (rest, actions) = parse_actions(&bytes, version(12)).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(ParsedAction::SelectSubgroupIndex(2), actions[0]);
+        assert!(matches!(actions[1], ParsedAction::SelectGroundItem(_)));
+    }
+
+    #[test]
+    fn reforged_actions() {
+        let bytes = [
+            [0x1B, 0x01, 0x07, 0x5A, 0x00, 0x00, 0x08, 0x5A, 0x00, 0x00].as_slice(),
+            &[0x75, 0x06],
+            &[0x78, b'T', 0x00],
+            b"AAE==QhNmAA==\0",
+            &[0x00; 4],
+            &[0x7A],
+            &[0x00; 20],
+            &[0x7B],
+            &[0x00; 16],
+        ]
+        .concat();
+        let (rest, actions) = parse_actions(&bytes, version(32)).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(5, actions.len());
+        assert_eq!(
+            ParsedAction::SelectUnit(UnitSelection {
+                object_1: 0x5A07,
+                object_2: 0x5A08,
+            }),
+            actions[0]
+        );
+        assert_eq!(
+            ParsedAction::ArrowKey(ArrowKeyAction {
+                key: ArrowKey::Up,
+                pressed: true,
+            }),
+            actions[1]
+        );
+        assert_eq!(
+            ParsedAction::SyncData(SyncDataAction {
+                prefix: "T".to_string(),
+                data: "AAE==QhNmAA==".to_string(),
+                unknown: 0,
+            }),
+            actions[2]
+        );
+        assert!(matches!(
+            actions[3],
+            ParsedAction::Opaque { kind: 0x7A, .. }
+        ));
+        assert_eq!(ParsedAction::Data([0; 16]), actions[4]);
+    }
+
+    #[test]
+    fn cheats() {
+        let bytes = [
+            [0x2D, 0x00, 0xF4, 0x01, 0x00, 0x00].as_slice(), // greedisgood 500
+            &[0x26],
+            &[0x2E],
+            &12f32.to_le_bytes(),
+        ]
+        .concat();
+        let (rest, actions) = parse_actions(&bytes, version(32)).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            vec![
+                ParsedAction::Cheat(Cheat::GreedIsGood(500)),
+                ParsedAction::Cheat(Cheat::WhosYourDaddy),
+                ParsedAction::Cheat(Cheat::DayLightSavings(12.0)),
+            ],
+            actions
+        );
+    }
+
+    #[test]
+    fn trigger_actions() {
+        let bytes = [
+            [0x60, 0x71, 0x41, 0x00, 0x00, 0x71, 0x41, 0x00, 0x00].as_slice(),
+            b"-ar\0",
+            &[
+                0x62, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+            ],
+        ]
+        .concat();
+        let (rest, actions) = parse_actions(&bytes, version(32)).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            vec![
+                ParsedAction::MapTriggerChat(MapTriggerChatAction {
+                    trigger_1: 0x4171,
+                    trigger_2: 0x4171,
+                    text: "-ar".to_string(),
+                }),
+                ParsedAction::ScenarioTrigger(ScenarioTriggerAction {
+                    trigger_1: 1,
+                    trigger_2: 2,
+                    counter: 3,
+                }),
+            ],
+            actions
+        );
+    }
+
+    #[test]
+    fn unknown_action_stops_parsing() {
+        let bytes = [0x01, 0xFE, 0x01, 0x02];
+        let (rest, actions) = parse_actions(&bytes, version(32)).unwrap();
+        assert_eq!(&bytes[1..], rest);
+        assert_eq!(
+            vec![ParsedAction::Pause, ParsedAction::Unknown(0xFE)],
+            actions
+        );
+    }
+
+    #[test]
+    fn truncated_action_is_invalid() {
+        // a set speed action without its speed
+        let bytes = [0x01, 0x03];
+        let (rest, actions) = parse_actions(&bytes, version(32)).unwrap();
+        assert_eq!(&bytes[1..], rest);
+        assert_eq!(
+            vec![ParsedAction::Pause, ParsedAction::Invalid(0x03)],
+            actions
+        );
+    }
 }
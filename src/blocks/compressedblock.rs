@@ -1,51 +1,174 @@
+use crate::error::W3rsError;
+use crate::metadata::replay::GameVersion;
+use crate::utils::crc32;
 use flate2::read::ZlibDecoder;
+use flate2::{Compress, Compression, FlushCompress};
 use nom::bytes::complete::take;
+use nom::combinator::map;
 use nom::multi::many0;
-use nom::{number::complete::le_u16, IResult};
+use nom::sequence::pair;
+use nom::{
+    number::complete::{le_u16, le_u32},
+    IResult,
+};
+use std::borrow::Cow;
 use std::io;
 use std::io::Read;
 
+/// Size of a decompressed block, the last one is padded with zeros
+const BLOCK_SIZE: usize = 8192;
+
 #[derive(PartialEq, Eq)]
-pub(crate) struct CompressedDataBlock {
-    pub block_size: u16,
-    pub block_decompressed_size: u16,
-    pub compressed: Vec<u8>,
+pub(crate) struct CompressedDataBlock<'a> {
+    pub block_size: u32,
+    pub block_decompressed_size: u32,
+    /// As read from the replay, see `checksum` to compute it
+    pub stored_checksum: u32,
+    /// Borrowed from the replay file when parsing, owned when compressing
+    pub compressed: Cow<'a, [u8]>,
 }
 
-impl CompressedDataBlock {
+impl CompressedDataBlock<'_> {
     pub fn inflate(&self) -> Result<Vec<u8>, io::Error> {
         let mut decoded = Vec::with_capacity(self.block_decompressed_size as usize);
         let mut decoder = ZlibDecoder::new(&self.compressed[..]);
         decoder.read_to_end(&mut decoded)?;
         Ok(decoded)
     }
+
+    /// Fastest compression and a sync-flushed (not finished) zlib stream, like the game.
+    /// flate2 uses a bigger memory level though, compressed bytes can differ from the original
+    pub fn compress(
+        data: &[u8],
+        version: GameVersion,
+    ) -> Result<CompressedDataBlock<'static>, io::Error> {
+        let mut compress = Compress::new(Compression::fast(), true);
+        let mut compressed = Vec::with_capacity(data.len() * 2 + 64);
+        compress.compress_vec(data, &mut compressed, FlushCompress::Sync)?;
+        if compress.total_in() != data.len() as u64 {
+            return Err(io::Error::other("could not compress data block"));
+        }
+        let mut block = CompressedDataBlock {
+            block_size: compressed.len() as u32,
+            block_decompressed_size: data.len() as u32,
+            stored_checksum: 0,
+            compressed: Cow::Owned(compressed),
+        };
+        block.stored_checksum = block.checksum(version);
+        Ok(block)
+    }
+
+    fn header(&self, version: GameVersion) -> Vec<u8> {
+        let mut header = Vec::with_capacity(12);
+        if version.has_wide_block_header() {
+            header.extend_from_slice(&self.block_size.to_le_bytes());
+            header.extend_from_slice(&self.block_decompressed_size.to_le_bytes());
+        } else {
+            header.extend_from_slice(&(self.block_size as u16).to_le_bytes());
+            header.extend_from_slice(&(self.block_decompressed_size as u16).to_le_bytes());
+        }
+        header.extend_from_slice(&[0; 4]);
+        header
+    }
+
+    /// Both CRC32 (header with a zeroed checksum, then compressed data) folded to 16 bits
+    pub fn checksum(&self, version: GameVersion) -> u32 {
+        fn fold(crc: u32) -> u32 {
+            (crc ^ (crc >> 16)) & 0xFFFF
+        }
+        fold(crc32(&self.header(version))) | fold(crc32(&self.compressed)) << 16
+    }
+}
+
+/// Splits the decompressed game data into padded blocks, the opposite of `deflate_game`
+pub(crate) fn compress_game(
+    data: &[u8],
+    version: GameVersion,
+) -> Result<Vec<CompressedDataBlock<'static>>, io::Error> {
+    data.chunks(BLOCK_SIZE)
+        .map(|chunk| {
+            let mut padded = chunk.to_vec();
+            padded.resize(BLOCK_SIZE, 0);
+            CompressedDataBlock::compress(&padded, version)
+        })
+        .collect()
 }
 
-pub(crate) fn deflate_game(blocks: &[CompressedDataBlock]) -> Result<Vec<u8>, io::Error> {
-    let mut decoded = Vec::new();
+pub(crate) fn write_compressed_data_blocks(
+    out: &mut Vec<u8>,
+    blocks: &[CompressedDataBlock],
+    version: GameVersion,
+) {
     for block in blocks {
-        decoded.extend(block.inflate()?);
+        let mut header = block.header(version);
+        let len = header.len();
+        header[len - 4..].copy_from_slice(&block.stored_checksum.to_le_bytes());
+        out.extend_from_slice(&header);
+        out.extend_from_slice(&block.compressed);
     }
-    Ok(decoded)
 }
 
-pub(crate) fn compressed_data_blocks(input: &[u8]) -> IResult<&[u8], Vec<CompressedDataBlock>> {
-    many0(compressed_data_block)(input)
+pub(crate) fn deflate_game(blocks: &[CompressedDataBlock]) -> Result<Vec<u8>, W3rsError> {
+    match deflate_intact_blocks(blocks) {
+        (decoded, None) => Ok(decoded),
+        (_, Some(failure)) => Err(failure),
+    }
 }
 
-fn compressed_data_block(input: &[u8]) -> IResult<&[u8], CompressedDataBlock> {
-    let (rest, block_size) = le_u16(input)?;
-    let (rest, _) = take(2usize)(rest)?;
-    let (rest, block_decompressed_size) = le_u16(rest)?;
-    let (rest, _) = take(4usize)(rest)?; // str ignored
-    let (rest, _) = take(2usize)(rest)?; // bytes ignored
+/// Inflates blocks up to the first one that can't be: returns what could be decoded, and the failure
+pub(crate) fn deflate_intact_blocks(
+    blocks: &[CompressedDataBlock],
+) -> (Vec<u8>, Option<W3rsError>) {
+    let mut decoded = Vec::with_capacity(
+        blocks
+            .iter()
+            .map(|b| b.block_decompressed_size as usize)
+            .sum(),
+    );
+    #[cfg(feature = "parallel")]
+    let inflated: Vec<_> = {
+        use rayon::prelude::*;
+        blocks
+            .par_iter()
+            .map(CompressedDataBlock::inflate)
+            .collect()
+    };
+    // lazily, to stop at the first failure
+    #[cfg(not(feature = "parallel"))]
+    let inflated = blocks.iter().map(CompressedDataBlock::inflate);
+    for (i, data) in inflated.into_iter().enumerate() {
+        match data {
+            Ok(data) => decoded.extend(data),
+            Err(source) => return (decoded, Some(W3rsError::Deflate { block: i, source })),
+        }
+    }
+    (decoded, None)
+}
+
+pub(crate) fn compressed_data_blocks(
+    version: GameVersion,
+) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<CompressedDataBlock<'_>>> {
+    move |input| many0(|i| compressed_data_block(i, version))(input)
+}
+
+pub(crate) fn compressed_data_block(
+    input: &[u8],
+    version: GameVersion,
+) -> IResult<&[u8], CompressedDataBlock<'_>> {
+    let (rest, (block_size, block_decompressed_size)) = if version.has_wide_block_header() {
+        pair(le_u32, le_u32)(input)?
+    } else {
+        pair(map(le_u16, u32::from), map(le_u16, u32::from))(input)?
+    };
+    let (rest, stored_checksum) = le_u32(rest)?;
     let (rest, compressed) = take(block_size as usize)(rest)?;
     Ok((
         rest,
         CompressedDataBlock {
             block_size,
             block_decompressed_size,
-            compressed: compressed.to_vec(),
+            stored_checksum,
+            compressed: Cow::Borrowed(compressed),
         },
     ))
 }
@@ -53,14 +176,18 @@ fn compressed_data_block(input: &[u8]) -> IResult<&[u8], CompressedDataBlock> {
 #[cfg(test)]
 mod tests {
     use crate::blocks::compressedblock::{compressed_data_blocks, deflate_game};
-    use crate::metadata::replay::parse_header;
+    use crate::metadata::replay::{parse_header, GameVersion, ReplayHeader};
     use crate::tests::replay_bytes;
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use std::io::Write;
 
     #[test]
     fn data_blocks_test() {
         let file = replay_bytes("reforged2010.w3g");
         let (rest, headers) = parse_header(&file).unwrap();
-        let (rest, blocks) = compressed_data_blocks(rest).unwrap();
+        let version = ReplayHeader::from(&headers).game_version();
+        let (rest, blocks) = compressed_data_blocks(version)(rest).unwrap();
         assert_eq!(0, rest.len());
         assert_eq!(headers.compressed_data_block_count as usize, blocks.len());
         let decoded = deflate_game(&blocks).unwrap();
@@ -72,4 +199,24 @@ mod tests {
             decoded.len() as u64
         );
     }
+
+    #[test]
+    fn classic_data_blocks_test() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[42u8; 8192]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let mut file = Vec::new();
+        file.extend_from_slice(&(compressed.len() as u16).to_le_bytes());
+        file.extend_from_slice(&8192u16.to_le_bytes());
+        file.extend_from_slice(&[0; 4]); // checksum
+        file.extend_from_slice(&compressed);
+        let version = GameVersion {
+            patch: 26,
+            build_no: 6059,
+        };
+        let (rest, blocks) = compressed_data_blocks(version)(&file).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(1, blocks.len());
+        assert_eq!(vec![42u8; 8192], deflate_game(&blocks).unwrap());
+    }
 }
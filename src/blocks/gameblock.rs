@@ -1,10 +1,13 @@
-use crate::blocks::chat::{player_chat_msg, PlayerChatMsgBlock};
-use crate::blocks::command::{parse_command, CommandData};
+use crate::blocks::chat::{player_chat_msg, write_player_chat_msg, PlayerChatMsgBlock};
+use crate::blocks::command::{parse_command, write_command, CommandData};
+use crate::error::NomError;
+use crate::event::{LeaveReason, LeaveResult};
+use crate::metadata::replay::GameVersion;
 use nom::bytes::complete::take;
-use nom::combinator::map_res;
+use nom::combinator::{all_consuming, verify};
 use nom::multi::many0;
 use nom::{
-    number::complete::{le_u16, le_u8},
+    number::complete::{le_u16, le_u32, le_u8},
     IResult,
 };
 use std::convert::TryInto;
@@ -14,16 +17,26 @@ pub(crate) enum GameBlock {
     Leave(LeaveGameBlock),
     TimeSlot(TimeSlotBlock),
     PlayerChatMsg(PlayerChatMsgBlock),
-    Ignored,
-    Unknown,
+    /// 0x1A, 0x1B and 0x1C (Reforged), right before the first time slot
+    GameStart(GameStartBlock),
+    /// 0x22, after every time slot
+    Checksum(ChecksumBlock),
+    /// 0x23, meaning unknown
+    Unknown023([u8; 10]),
+    /// 0x2F, when a player got kicked or the map is revealed
+    GameEndCountdown(CountdownBlock),
+    Unknown(u8),
 }
 
 impl GameBlock {
     #[allow(dead_code)]
     pub fn should_display(&self) -> bool {
         match self {
-            GameBlock::Unknown | GameBlock::Ignored => false,
-            GameBlock::TimeSlot(ts) => ts.command.is_some(),
+            GameBlock::Unknown(_)
+            | GameBlock::GameStart(_)
+            | GameBlock::Checksum(_)
+            | GameBlock::Unknown023(_) => false,
+            GameBlock::TimeSlot(ts) => !ts.commands.is_empty(),
             _ => true,
         }
     }
@@ -34,81 +47,157 @@ pub struct LeaveGameBlock {
     pub player_id: u8,
     pub reason: [u8; 4],
     pub result: [u8; 4],
+    pub(crate) unknown: [u8; 4],
 }
 
 impl LeaveGameBlock {
+    pub fn leave_reason(&self) -> LeaveReason {
+        LeaveReason::from(u32::from_le_bytes(self.reason))
+    }
+
+    pub fn leave_result(&self) -> LeaveResult {
+        LeaveResult::from(u32::from_le_bytes(self.result))
+    }
+
     pub fn is_draw(&self) -> bool {
-        self.result[0] == 10 // no matter if reason[0] is 0x01 or 0x0C
+        self.leave_result() == LeaveResult::Draw // no matter if reason is 0x01 or 0x0C
     }
     /// true if we're SURE the player won, false otherwise
     pub fn player_won(&self) -> bool {
-        self.result[0] == 9
+        self.leave_result() == LeaveResult::Won
     }
     /// true if we're SURE the player lost, false otherwise
     pub fn player_lost(&self) -> bool {
-        self.result[0] == 8 || self.result[0] == 13
+        self.leave_result() == LeaveResult::Lost
     }
 }
 
 #[derive(Debug)]
 pub struct TimeSlotBlock {
+    /// 0x1F, 0x1E on old replays
+    pub(crate) kind: u8,
     // byte_count: u16,
     pub time_increment: u16,
-    pub command: Option<CommandData>,
+    pub commands: Vec<CommandData>,
+}
+
+#[derive(Debug)]
+pub struct GameStartBlock {
+    pub(crate) kind: u8,
+    /// Always 1
+    pub(crate) unknown: u32,
+}
+
+#[derive(Debug)]
+pub struct ChecksumBlock {
+    /// Always 4 bytes so far
+    pub(crate) payload: Vec<u8>,
 }
 
-pub(crate) fn parse_time_blocks(input: &[u8]) -> IResult<&[u8], Option<CommandData>> {
-    if input.is_empty() {
-        Ok((&[], None))
-    } else {
-        parse_command(input).map(|(b, cd)| (b, Some(cd)))
+impl ChecksumBlock {
+    pub fn checksum(&self) -> Option<u32> {
+        Some(u32::from_le_bytes(self.payload.as_slice().try_into().ok()?))
     }
 }
 
-pub(crate) fn parse_game_blocks(input: &[u8]) -> IResult<&[u8], Vec<GameBlock>> {
-    many0(parsed_game_block)(input)
+#[derive(Debug)]
+pub struct CountdownBlock {
+    /// 0 while counting down, 1 when over
+    pub(crate) mode: u32,
+    pub countdown_secs: u32,
+}
+
+impl CountdownBlock {
+    pub fn is_over(&self) -> bool {
+        self.mode == 1
+    }
+}
+
+pub(crate) fn parse_time_blocks(
+    input: &[u8],
+    version: GameVersion,
+) -> IResult<&[u8], Vec<CommandData>> {
+    many0(|i| parse_command(i, version))(input)
+}
+
+/// Parses blocks up to the end of `input`, or up to the first one that can't be parsed
+pub(crate) fn parse_game_blocks(
+    input: &[u8],
+    version: GameVersion,
+) -> (Vec<GameBlock>, Option<NomError<'_>>) {
+    let mut rest = input;
+    let mut blocks = Vec::new();
+    while !rest.is_empty() {
+        match parsed_game_block(rest, version) {
+            Ok((next, block)) => {
+                blocks.push(block);
+                rest = next;
+            }
+            Err(e) => return (blocks, Some(e)),
+        }
+    }
+    (blocks, None)
 }
 
-fn parsed_game_block(input: &[u8]) -> IResult<&[u8], GameBlock> {
+pub(crate) fn parsed_game_block(input: &[u8], version: GameVersion) -> IResult<&[u8], GameBlock> {
     let (rest, kind) = le_u8(input)?;
     match kind {
         23 => parse_leave_block(rest),
-        26 | 27 => ignore_4(rest),
-        30 | 31 => time_slot_block(rest),
+        26..=28 => game_start_block(rest, kind),
+        30 | 31 => time_slot_block(rest, kind, version),
         32 => player_chat_msg(rest),
-        34 => unknown_022(rest),
+        34 => checksum_block(rest),
         35 => unknown_023(rest),
-        47 => forced_game_end_cd(rest),
-        _ => Ok((rest, GameBlock::Unknown)),
+        47 => countdown_block(rest),
+        _ => Ok((rest, GameBlock::Unknown(kind))),
     }
 }
 
-fn unknown_022(input: &[u8]) -> IResult<&[u8], GameBlock> {
+fn game_start_block(input: &[u8], kind: u8) -> IResult<&[u8], GameBlock> {
+    let (rest, unknown) = le_u32(input)?;
+    Ok((rest, GameBlock::GameStart(GameStartBlock { kind, unknown })))
+}
+
+fn checksum_block(input: &[u8]) -> IResult<&[u8], GameBlock> {
     let (rest, length) = le_u8(input)?;
-    let (rest, _) = take(length as usize)(rest)?;
-    Ok((rest, GameBlock::Unknown))
+    let (rest, payload) = take(length as usize)(rest)?;
+    Ok((
+        rest,
+        GameBlock::Checksum(ChecksumBlock {
+            payload: payload.to_vec(),
+        }),
+    ))
 }
 
 fn unknown_023(input: &[u8]) -> IResult<&[u8], GameBlock> {
-    let (rest, _) = take(8usize)(input)?;
-    Ok((rest, GameBlock::Unknown))
+    let (rest, data) = take(10usize)(input)?;
+    Ok((rest, GameBlock::Unknown023(data.try_into().unwrap())))
 }
 
-fn forced_game_end_cd(input: &[u8]) -> IResult<&[u8], GameBlock> {
-    let (rest, _) = take(8usize)(input)?;
-    Ok((rest, GameBlock::Unknown))
+fn countdown_block(input: &[u8]) -> IResult<&[u8], GameBlock> {
+    let (rest, mode) = le_u32(input)?;
+    let (rest, countdown_secs) = le_u32(rest)?;
+    Ok((
+        rest,
+        GameBlock::GameEndCountdown(CountdownBlock {
+            mode,
+            countdown_secs,
+        }),
+    ))
 }
 
-fn time_slot_block(input: &[u8]) -> IResult<&[u8], GameBlock> {
-    let (rest, byte_count) = le_u16(input)?;
+fn time_slot_block(input: &[u8], kind: u8, version: GameVersion) -> IResult<&[u8], GameBlock> {
+    let (rest, byte_count) = verify(le_u16, |count| *count >= 2)(input)?;
     let (rest, time_increment) = le_u16(rest)?;
-    let (rest, command) = map_res(take((byte_count - 2) as usize), parse_time_blocks)(rest)?;
+    let (rest, commands) = take((byte_count - 2) as usize)(rest)?;
+    let (_, commands) = all_consuming(|i| parse_time_blocks(i, version))(commands)?;
     Ok((
         rest,
         GameBlock::TimeSlot(TimeSlotBlock {
+            kind,
             // byte_count,
             time_increment,
-            command: command.1,
+            commands,
         }),
     ))
 }
@@ -117,18 +206,137 @@ fn parse_leave_block(input: &[u8]) -> IResult<&[u8], GameBlock> {
     let (rest, reason) = take(4usize)(input)?;
     let (rest, player_id) = le_u8(rest)?;
     let (rest, result) = take(4usize)(rest)?;
-    let (rest, _) = take(4usize)(rest)?;
+    let (rest, unknown) = take(4usize)(rest)?;
     Ok((
         rest,
         GameBlock::Leave(LeaveGameBlock {
             player_id,
             reason: reason[0..4].try_into().unwrap(),
             result: result[0..4].try_into().unwrap(),
+            unknown: unknown[0..4].try_into().unwrap(),
         }),
     ))
 }
 
-fn ignore_4(input: &[u8]) -> IResult<&[u8], GameBlock> {
-    let (rest, _) = take(4usize)(input)?;
-    Ok((rest, GameBlock::Ignored))
+pub(crate) fn write_game_blocks(out: &mut Vec<u8>, blocks: &[GameBlock]) {
+    for block in blocks {
+        match block {
+            GameBlock::Leave(leave) => {
+                out.push(23);
+                out.extend_from_slice(&leave.reason);
+                out.push(leave.player_id);
+                out.extend_from_slice(&leave.result);
+                out.extend_from_slice(&leave.unknown);
+            }
+            GameBlock::TimeSlot(ts) => {
+                let mut commands = Vec::new();
+                for command in &ts.commands {
+                    write_command(&mut commands, command);
+                }
+                out.push(ts.kind);
+                out.extend_from_slice(&(commands.len() as u16 + 2).to_le_bytes());
+                out.extend_from_slice(&ts.time_increment.to_le_bytes());
+                out.extend_from_slice(&commands);
+            }
+            GameBlock::PlayerChatMsg(msg) => {
+                out.push(32);
+                write_player_chat_msg(out, msg);
+            }
+            GameBlock::GameStart(start) => {
+                out.push(start.kind);
+                out.extend_from_slice(&start.unknown.to_le_bytes());
+            }
+            GameBlock::Checksum(checksum) => {
+                out.push(34);
+                out.push(checksum.payload.len() as u8);
+                out.extend_from_slice(&checksum.payload);
+            }
+            GameBlock::Unknown023(data) => {
+                out.push(35);
+                out.extend_from_slice(data);
+            }
+            GameBlock::GameEndCountdown(countdown) => {
+                out.push(47);
+                out.extend_from_slice(&countdown.mode.to_le_bytes());
+                out.extend_from_slice(&countdown.countdown_secs.to_le_bytes());
+            }
+            GameBlock::Unknown(kind) => out.push(*kind),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::gameblock::{parse_game_blocks, write_game_blocks, GameBlock};
+    use crate::event::Event;
+    use crate::game::{non_noisy, Game};
+    use crate::metadata::replay::GameVersion;
+    use crate::tests::replay;
+
+    #[test]
+    fn decode_game_blocks() {
+        let mut game = Game::parse(replay("reforged_release.w3g"));
+        assert!(!game
+            .blocks
+            .iter()
+            .any(|b| matches!(b, GameBlock::Unknown(_))));
+        let starts: Vec<u8> = game
+            .blocks
+            .iter()
+            .filter_map(|b| match b {
+                GameBlock::GameStart(start) => Some(start.kind),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(vec![0x1A, 0x1B, 0x1C], starts);
+        let events = game.events();
+        assert!(matches!(events[0].event, Event::GameStarted));
+        assert_eq!(None, events[0].player_id);
+        let checksums = events
+            .iter()
+            .filter(|e| matches!(e.event, Event::Checksum { .. }))
+            .count();
+        assert_eq!(game.checksums().len(), checksums);
+        // too many of them
+        assert!(!events
+            .iter()
+            .filter(non_noisy)
+            .any(|e| matches!(e.event, Event::Checksum { .. })));
+        let time_slots = game
+            .blocks
+            .iter()
+            .filter(|b| matches!(b, GameBlock::TimeSlot(_)))
+            .count();
+        assert_eq!(time_slots, checksums);
+        // none in the fixtures
+        game.blocks.push(GameBlock::Unknown023([1; 10]));
+        let last = game.events().pop().unwrap();
+        assert_eq!(None, last.player_id);
+        assert_eq!(Event::Unknown023 { data: [1; 10] }, last.event);
+    }
+
+    #[test]
+    fn game_end_countdown() {
+        let version = GameVersion {
+            patch: 32,
+            build_no: 6102,
+        };
+        let mut data = vec![0x2F, 0, 0, 0, 0, 30, 0, 0, 0];
+        data.extend_from_slice(&[0x23, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        data.extend_from_slice(&[0x2F, 1, 0, 0, 0, 0, 0, 0, 0]);
+        let (blocks, failure) = parse_game_blocks(&data, version);
+        assert!(failure.is_none());
+        assert_eq!(3, blocks.len());
+        match &blocks[0] {
+            GameBlock::GameEndCountdown(countdown) => {
+                assert!(!countdown.is_over());
+                assert_eq!(30, countdown.countdown_secs);
+            }
+            other => panic!("unexpected block {:?}", other),
+        }
+        assert!(matches!(&blocks[2], GameBlock::GameEndCountdown(c) if c.is_over()));
+        let mut written = Vec::new();
+        write_game_blocks(&mut written, &blocks);
+        assert_eq!(data, written);
+    }
 }
@@ -0,0 +1,148 @@
+//! Compares recordings of the same game, saved by different players
+use crate::blocks::gameblock::GameBlock;
+use crate::game::Game;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Comparison {
+    /// Not the same random seed or not the same players
+    DifferentGames,
+    /// Checksums match up to the end of the shortest recording
+    InSync {
+        ticks: usize,
+    },
+    Desync(Desync),
+}
+
+/// The first tick at which two recordings disagree on the game state
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Desync {
+    /// Number of time slots since the start of the game
+    pub tick: usize,
+    pub time: Duration,
+    pub checksums: (u32, u32),
+}
+
+/// The checksum of the game state after a time slot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TickChecksum {
+    pub tick: usize,
+    pub time: Duration,
+    pub checksum: u32,
+}
+
+impl Game {
+    /// Same random seed and same players
+    pub fn is_same_game(&self, other: &Game) -> bool {
+        let players = |game: &Game| {
+            let mut players: Vec<(u8, String)> = game
+                .players
+                .iter()
+                .map(|p| (p.id, p.name.clone()))
+                .collect();
+            players.sort();
+            players
+        };
+        self.pos.random_seed == other.pos.random_seed && players(self) == players(other)
+    }
+
+    pub fn checksums(&self) -> Vec<TickChecksum> {
+        let mut tick = 0;
+        let mut time = Duration::from_millis(0);
+        let mut checksums = Vec::new();
+        for block in &self.blocks {
+            match block {
+                GameBlock::TimeSlot(ts) => {
+                    tick += 1;
+                    time += Duration::from_millis(ts.time_increment as u64);
+                }
+                GameBlock::Checksum(block) => {
+                    if let Some(checksum) = block.checksum() {
+                        checksums.push(TickChecksum {
+                            tick,
+                            time,
+                            checksum,
+                        });
+                    }
+                }
+                _ => {}
+            }
+        }
+        checksums
+    }
+
+    /// Finds the first tick where this recording and `other` disagree
+    pub fn compare(&self, other: &Game) -> Comparison {
+        if !self.is_same_game(other) {
+            return Comparison::DifferentGames;
+        }
+        let checksums = self.checksums();
+        let others = other.checksums();
+        for (mine, theirs) in checksums.iter().zip(&others) {
+            if mine.tick != theirs.tick || mine.checksum != theirs.checksum {
+                return Comparison::Desync(Desync {
+                    tick: mine.tick.min(theirs.tick),
+                    time: mine.time.min(theirs.time),
+                    checksums: (mine.checksum, theirs.checksum),
+                });
+            }
+        }
+        Comparison::InSync {
+            ticks: checksums.len().min(others.len()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::gameblock::GameBlock;
+    use crate::desync::{Comparison, Desync};
+    use crate::game::Game;
+    use crate::tests::replay_bytes;
+
+    #[test]
+    fn compare_recordings() {
+        let bytes = replay_bytes("reforged2010.w3g");
+        let game = Game::from_bytes(&bytes).unwrap();
+        let mut other = Game::from_bytes(&bytes).unwrap();
+        let ticks = game.checksums().len();
+        assert!(ticks > 1000);
+        assert_eq!(Comparison::InSync { ticks }, game.compare(&other));
+
+        // another perspective of the same game, diverging at the 500th checksum
+        let diverging = other
+            .blocks
+            .iter_mut()
+            .filter_map(|b| match b {
+                GameBlock::Checksum(c) => Some(c),
+                _ => None,
+            })
+            .nth(500)
+            .unwrap();
+        diverging.payload = vec![1, 2, 3, 4];
+        let expected = game.checksums()[500];
+        assert_eq!(
+            Comparison::Desync(Desync {
+                tick: expected.tick,
+                time: expected.time,
+                checksums: (expected.checksum, 0x04030201),
+            }),
+            game.compare(&other)
+        );
+
+        // a shorter recording, saved by a player who left early
+        let mut short = Game::from_bytes(&bytes).unwrap();
+        short.truncate(expected.time);
+        assert!(matches!(
+            game.compare(&short),
+            Comparison::InSync { ticks } if ticks <= 501
+        ));
+    }
+
+    #[test]
+    fn different_games() {
+        let game = Game::from_bytes(&replay_bytes("reforged1.w3g")).unwrap();
+        let other = Game::from_bytes(&replay_bytes("reforged2.w3g")).unwrap();
+        assert_eq!(Comparison::DifferentGames, game.compare(&other));
+    }
+}
@@ -22,7 +22,25 @@ impl Display for Action {
                     Ok(())
                 }
             }
+            #[allow(deprecated)]
             Action::Leave => write!(f, "left"),
+            Action::TransferResources {
+                to_player,
+                gold,
+                lumber,
+            } => write!(
+                f,
+                "sent {} gold and {} lumber to player {}",
+                gold, lumber, to_player
+            ),
+            Action::ChangeAlliance {
+                with_player,
+                options,
+            } => write!(
+                f,
+                "changed alliance with player {}: {:?}",
+                with_player, options
+            ),
             Action::TrainUpgrade(upgrade) => write!(f, "trained {:?}", upgrade),
             Action::TrainHero(hero) => write!(f, "trained {:?}", hero),
             Action::TrainUnit(unit) => write!(f, "trained {:?}", unit),
@@ -6,8 +6,20 @@ use crate::game::{Game, GameOutcome};
 impl Display for Game {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         let outcome = self.outcome();
-        writeln!(f, "Warcraft 3 Reforged game. {:?}", self.game_type())?;
-        writeln!(f, "\tMap: {}", self.map.name)?;
+        let edition = if self.header.game_version().has_wide_block_header() {
+            "Reforged"
+        } else if self.header.expansion {
+            "The Frozen Throne"
+        } else {
+            "Reign of Chaos"
+        };
+        writeln!(f, "Warcraft 3 {} game. {:?}", edition, self.game_type())?;
+        writeln!(f, "\tMap: {} (by {})", self.map.name, self.map.creator)?;
+        writeln!(
+            f,
+            "\tSpeed: {:?}, observers: {:?}",
+            self.settings.speed, self.settings.observer_mode
+        )?;
         for (team, players) in self.players_by_team() {
             let team_won = outcome == GameOutcome::Winner(team);
             write!(f, "\tTeam {:?}:", team + 1)?;
@@ -32,10 +44,12 @@ impl Display for GameBlock {
             GameBlock::Leave(left) => writeln!(
                 f,
                 "Player {} left {:?}|{:?}",
-                left.player_id, left.reason, left.result
+                left.player_id,
+                left.leave_reason(),
+                left.leave_result()
             )?,
             GameBlock::TimeSlot(ts_block) => {
-                if let Some(cmd) = &ts_block.command {
+                for cmd in &ts_block.commands {
                     writeln!(f, "Player {}:", cmd.player)?;
                     for action in &cmd.actions {
                         writeln!(f, "\t{:?}", action)?;
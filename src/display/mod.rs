@@ -19,24 +19,27 @@ pub fn live_display(game: &Game) {
         let time = event.time;
         sleep(time - last_time);
         last_time = time;
-        let color = game
-            .player(event.player_id)
+        let color = event
+            .player_id
+            .and_then(|id| game.player(id))
             .and_then(player_msg_color)
             .unwrap_or(Color::White);
+        let source = match event.player_id {
+            Some(id) => format!("Player {}", id),
+            None => "Game".to_string(),
+        };
         print!(
             "{}",
-            format!(
-                "[{}] Player {}: ",
-                format_duration(event.time),
-                event.player_id
-            )
-            .color(color)
+            format!("[{}] {}: ", format_duration(event.time), source).color(color)
         );
         match &event.event {
             Event::ChatMsg { addressee, message } => println!("{} {}", addressee, message),
-            Event::Action { selection, action } => {
+            Event::Action {
+                selection, action, ..
+            } => {
                 println!("{}", format!("{:?} {}", selection, action).color(color));
             }
+            other => println!("{:?}", other),
         };
     }
 }
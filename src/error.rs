@@ -0,0 +1,130 @@
+use nom::error::ErrorKind;
+use std::error::Error;
+use std::fmt;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::time::Duration;
+
+/// The step of the replay parsing pipeline that failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseStage {
+    Header,
+    CompressedDataBlocks,
+    Deflate,
+    GameMetadata,
+    Players,
+    ReforgedPlayers,
+    StartRecord,
+    PlayerSlots,
+    GamePos,
+    MapInfo,
+    GameBlocks,
+    Actions,
+}
+
+#[derive(Debug)]
+pub enum W3rsError {
+    /// The replay could not be read
+    Io(io::Error),
+    /// A parser failed.
+    /// `offset` is relative to the raw file for `Header` and `CompressedDataBlocks`,
+    /// to the decoded map info for `MapInfo`, and to the decompressed game data otherwise.
+    Parse {
+        stage: ParseStage,
+        offset: usize,
+        kind: ErrorKind,
+    },
+    /// A compressed data block could not be inflated
+    Deflate { block: usize, source: io::Error },
+    /// In strict mode, an action of `player` couldn't be decoded at `time` (game time)
+    UnknownAction {
+        kind: u8,
+        player: u8,
+        time: Duration,
+    },
+    /// In strict mode, the payload of a known action of `player` couldn't be decoded at `time`
+    InvalidAction {
+        kind: u8,
+        player: u8,
+        time: Duration,
+    },
+}
+
+impl W3rsError {
+    pub fn stage(&self) -> Option<ParseStage> {
+        match self {
+            W3rsError::Io(_) => None,
+            W3rsError::Parse { stage, .. } => Some(*stage),
+            W3rsError::Deflate { .. } => Some(ParseStage::Deflate),
+            W3rsError::UnknownAction { .. } | W3rsError::InvalidAction { .. } => {
+                Some(ParseStage::Actions)
+            }
+        }
+    }
+}
+
+impl Display for W3rsError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            W3rsError::Io(e) => write!(f, "could not read replay: {}", e),
+            W3rsError::Parse {
+                stage,
+                offset,
+                kind,
+            } => write!(
+                f,
+                "could not parse {:?} at offset {} ({:?})",
+                stage, offset, kind
+            ),
+            W3rsError::Deflate { block, source } => {
+                write!(f, "could not inflate data block {}: {}", block, source)
+            }
+            W3rsError::UnknownAction { kind, player, time } => write!(
+                f,
+                "unknown action 0x{:02X} of player {} at {:?}",
+                kind, player, time
+            ),
+            W3rsError::InvalidAction { kind, player, time } => write!(
+                f,
+                "could not parse action 0x{:02X} of player {} at {:?}",
+                kind, player, time
+            ),
+        }
+    }
+}
+
+impl Error for W3rsError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            W3rsError::Io(e) => Some(e),
+            W3rsError::Deflate { source, .. } => Some(source),
+            W3rsError::Parse { .. }
+            | W3rsError::UnknownAction { .. }
+            | W3rsError::InvalidAction { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for W3rsError {
+    fn from(e: io::Error) -> Self {
+        W3rsError::Io(e)
+    }
+}
+
+pub(crate) type NomError<'a> = nom::Err<nom::error::Error<&'a [u8]>>;
+
+/// Maps a nom error to a `W3rsError`, computing the offset relative to `input`
+pub(crate) fn failed_at(stage: ParseStage, input: &[u8]) -> impl Fn(NomError) -> W3rsError + '_ {
+    move |err| match err {
+        nom::Err::Error(e) | nom::Err::Failure(e) => W3rsError::Parse {
+            stage,
+            offset: (e.input.as_ptr() as usize).saturating_sub(input.as_ptr() as usize),
+            kind: e.code,
+        },
+        nom::Err::Incomplete(_) => W3rsError::Parse {
+            stage,
+            offset: input.len(),
+            kind: ErrorKind::Complete,
+        },
+    }
+}
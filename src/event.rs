@@ -1,6 +1,7 @@
-use crate::action::Action;
+use crate::action::{AbilityFlags, Action};
 use crate::blocks::chat::Addressee;
 use crate::blocks::command::GameComponent;
+use crate::map::GameSpeed;
 use std::time::Duration;
 
 #[derive(PartialEq, Clone, Debug)]
@@ -12,12 +13,114 @@ pub enum Event {
     Action {
         selection: Vec<GameComponent>,
         action: Action,
+        /// Only for abilities
+        flags: Option<AbilityFlags>,
     },
+    /// Chat received by the map's triggers: commands like `-ar` in custom maps
+    TriggerChat {
+        text: String,
+    },
+    /// Scenario triggers fired, with the trigger's object ids
+    ScenarioTrigger {
+        trigger_1: u32,
+        trigger_2: u32,
+        counter: u32,
+    },
+    /// Game time stops until the game resumes
+    Paused,
+    Resumed {
+        paused_for: Duration,
+    },
+    SpeedChanged {
+        speed: GameSpeed,
+    },
+    SpeedIncreased,
+    SpeedDecreased,
+    /// Started saving the game to `name`
+    Saving {
+        name: String,
+    },
+    Saved,
+    PlayerLeft {
+        reason: LeaveReason,
+        result: LeaveResult,
+    },
+    /// Game-wide, like the following ones: there's no `player_id`
+    GameStarted,
+    /// Checksum of the game state, after every time slot. Left out by `non_noisy`,
+    /// `Game::checksums` lists them with their tick
+    Checksum {
+        checksum: u32,
+    },
+    /// 0x23 block, meaning unknown
+    Unknown023 {
+        data: [u8; 10],
+    },
+    /// The game is about to end, for instance after the map got revealed
+    GameEndCountdown {
+        over: bool,
+        countdown: Duration,
+    },
+}
+
+/// Who closed the connection to the leaving player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaveReason {
+    /// Another player left
+    RemoteGame,
+    /// The player who saved the replay left
+    LocalGame,
+    /// Rare, like `RemoteGame`: most likely a disconnection
+    Disconnected,
+    Unknown(u32),
+}
+
+impl From<u32> for LeaveReason {
+    fn from(reason: u32) -> Self {
+        match reason {
+            0x01 => LeaveReason::RemoteGame,
+            0x0C => LeaveReason::LocalGame,
+            0x0E => LeaveReason::Disconnected,
+            reason => LeaveReason::Unknown(reason),
+        }
+    }
+}
+
+/// The state of the leaving player
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaveResult {
+    Disconnected,
+    /// Left before the game was over
+    Left,
+    Lost,
+    Won,
+    Draw,
+    ObserverLeft,
+    Unknown(u32),
+}
+
+impl From<u32> for LeaveResult {
+    fn from(result: u32) -> Self {
+        match result {
+            0x01 => LeaveResult::Disconnected,
+            0x07 => LeaveResult::Left,
+            0x08 | 0x0D => LeaveResult::Lost,
+            0x09 => LeaveResult::Won,
+            0x0A => LeaveResult::Draw,
+            0x0B => LeaveResult::ObserverLeft,
+            result => LeaveResult::Unknown(result),
+        }
+    }
 }
 
 #[derive(PartialEq, Clone, Debug)]
 pub struct GameEvent {
-    pub player_id: u8,
+    /// `None` for game-wide events. Was a plain `u8` before game-wide events were added:
+    /// 0 can't tell them apart, it's also the id of every computer player
+    pub player_id: Option<u8>,
+    /// Wall-clock time since the start of the game, pauses included
     pub time: Duration,
+    /// Pauses excluded
+    pub game_time: Duration,
     pub event: Event,
 }
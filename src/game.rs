@@ -1,34 +1,63 @@
 use crate::action::{from_parsed_action, Action};
-use crate::blocks::chat::ChatMsgBlock;
-use crate::blocks::command::{GameComponent, SelectedComponent};
-use crate::blocks::compressedblock::{compressed_data_blocks, deflate_game};
-use crate::blocks::gameblock::{parse_game_blocks, GameBlock, LeaveGameBlock};
+use crate::blocks::chat::{Addressee, ChatMsgBlock};
+use crate::blocks::command::{GameComponent, ParsedAction, SelectedComponent};
+use crate::blocks::compressedblock::{
+    compress_game, compressed_data_block, compressed_data_blocks, deflate_game,
+    deflate_intact_blocks, write_compressed_data_blocks,
+};
+use crate::blocks::gameblock::{parse_game_blocks, write_game_blocks, GameBlock, LeaveGameBlock};
+use crate::error::{failed_at, ParseStage, W3rsError};
 use crate::event::{Event, GameEvent};
-use crate::map::{parse_map_info, MapInfo};
+use crate::map::{parse_map_info, GameSettings, GameSpeed, MapInfo};
 use crate::metadata::game::{
-    parse_game_metadata, parse_game_pos, parse_start_record, GamePosData, GameStartRecord,
+    parse_game_metadata, parse_game_pos, parse_start_record, write_game_metadata, write_game_pos,
+    write_start_record, GameFlags, GameMetaData, GamePosData, GameStartRecord, Language,
+};
+use crate::metadata::player::{
+    parse_players, parse_players_reforged, parse_players_slots, write_players,
+    write_players_reforged, write_players_slots, PlayerMetaData, PlayerSlotMetaData,
+    ReforgedPlayerMetaData, ReforgedRecord,
+};
+use crate::metadata::replay::{
+    parse_header, write_header, FileMetaData, GameVersion, ReplayHeader, ReplayMetaData,
 };
-use crate::metadata::player::{parse_players, parse_players_reforged, parse_players_slots};
-use crate::metadata::replay::parse_header;
-use crate::player::Player;
+use crate::player::{AiDifficulty, Player, Slot};
 use itertools::Itertools;
 use std::collections::HashMap;
 use std::fmt::Debug;
 use std::fs;
+use std::io::{Read, Write};
 use std::path::Path;
 use std::time::Duration;
 
 #[derive(Debug)]
 pub struct Game {
+    pub header: ReplayHeader,
     pub name: String,
-    pub game_type: Vec<String>,
+    pub flags: GameFlags,
+    pub language: Language,
     pub game_start_record: GameStartRecord,
     pub players: Vec<Player>,
+    /// Every lobby slot, including open and closed ones
+    pub slots: Vec<Slot>,
     pub pos: GamePosData,
     pub map: MapInfo,
+    pub settings: GameSettings,
     pub(crate) blocks: Vec<GameBlock>,
+    pub(crate) metadata: GameMetaData,
+    pub(crate) players_metadata: Vec<PlayerMetaData>,
+    pub(crate) reforged_records: Vec<ReforgedRecord>,
+    pub(crate) slot_records: Vec<PlayerSlotMetaData>,
+    /// Lenient mode only: game time at which the replay data stops, if incomplete
+    pub truncated_at: Option<Duration>,
+    /// Lenient mode only: what prevented the replay from being read entirely
+    pub failures: Vec<W3rsError>,
 }
 
+const LEAVE_REMOTE_GAME: u8 = 0x01;
+const LEAVE_LOCAL_GAME: u8 = 0x0C;
+const LEAVE_RESULT_LEFT: u8 = 0x07;
+
 #[derive(Debug, PartialEq)]
 pub enum GameOutcome {
     Draw,
@@ -66,7 +95,10 @@ impl Game {
                 .collect();
             if team_leave_blocks.iter().any(|block| block.player_won()) {
                 return GameOutcome::Winner(team_id);
-            } else if team_leave_blocks.iter().all(|block| block.player_lost()) {
+            } else if !team_leave_blocks.is_empty()
+                && team_leave_blocks.iter().all(|block| block.player_lost())
+            {
+                // computers never leave: a team without leave blocks hasn't lost
                 teams_who_lost.push(team_id);
             }
         }
@@ -74,7 +106,7 @@ impl Game {
             return GameOutcome::Winner(
                 *(all_teams
                     .iter()
-                    .find(|t| !teams_who_lost.contains(&&t))
+                    .find(|t| !teams_who_lost.contains(t))
                     .unwrap()),
             );
         }
@@ -82,12 +114,47 @@ impl Game {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GameContext {
     Ladder,
     Custom,
     SinglePlayer,
-    Unknown(String),
+    /// A custom game on a map that isn't a melee one
+    Scenario,
+    Unknown(u8),
+}
+
+/// Summed as `u64`: amounts come straight from the replay
+#[derive(Debug, PartialEq, Default)]
+pub struct ResourceTransfers {
+    pub gold_sent: u64,
+    pub lumber_sent: u64,
+    pub gold_received: u64,
+    pub lumber_received: u64,
+}
+
+impl ResourceTransfers {
+    /// Gold received minus gold sent
+    pub fn net_gold(&self) -> i64 {
+        self.gold_received as i64 - self.gold_sent as i64
+    }
+
+    /// Lumber received minus lumber sent
+    pub fn net_lumber(&self) -> i64 {
+        self.lumber_received as i64 - self.lumber_sent as i64
+    }
+}
+
+/// Who paused the game, when and for how long
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pause {
+    pub paused_by: u8,
+    /// Wall-clock time
+    pub at: Duration,
+    pub game_time: Duration,
+    /// `None` if the game ended while paused
+    pub resumed_by: Option<u8>,
+    pub duration: Duration,
 }
 
 #[derive(Debug)]
@@ -102,73 +169,218 @@ pub enum GameType {
 
 impl Game {
     pub fn parse<P: AsRef<Path> + Debug>(file: P) -> Game {
-        println!("Read replay {:?}", file);
-        let file = fs::read(file).expect("Can read replay as bytes");
-        let (rest, _) = parse_header(&file[..]).expect("Could not parse replay header");
-        let (_, blocks) = compressed_data_blocks(rest).expect("Could not parse data blocks");
-        let decoded = deflate_game(&blocks)
-            .expect("Could not deflate blocks of replay, is zlib available on system?");
-        let (rest, metadata) =
-            parse_game_metadata(&decoded).expect("Could not parse game metadata");
-        let (rest, players_metadata) =
-            parse_players(rest).expect("could not parse players metadata");
-        // println!("rest is: {rest:?}");
-        let mut rest = rest;
-        if rest[0] != 0x19 {
-            // TODO: handle Reforged-style players (Protobuf w/ icon, etc.)
-            let (b, _players_reforged_metadata) =
-                parse_players_reforged(rest).expect("Could not parse players metadata (reforged)");
-            rest = b;
+        match Game::try_parse(&file) {
+            Ok(game) => game,
+            Err(e) => panic!("Could not parse replay {:?}: {}", file, e),
         }
+    }
 
-        let (rest, game_start_record) =
-            parse_start_record(rest).expect("Could not parse start record");
-        let (rest, players_slots) = parse_players_slots(game_start_record.slot_record_count)(rest)
-            .expect("Could not parse player slot");
-        let (rest, game_pos_data) = parse_game_pos(rest).expect("Could not parse game start pos");
-        let (_, map) = parse_map_info(&crate::utils::decode(&metadata.encoded_map_info)[..])
-            .expect("Could not decode map data");
-        let host = metadata.host.clone();
-        let (_, blocks) = parse_game_blocks(rest).expect("Could not parse data blocks");
-        let players: Vec<Player> = players_slots
-            .iter()
-            .flat_map(|slot| {
-                if host.id == slot.player_id {
-                    Some(Player {
-                        team_id: slot.team_id,
-                        id: host.id,
-                        name: host.name.clone(),
-                        race: slot.race.clone(),
-                        color: slot.color,
-                        host: true,
-                    })
-                } else {
-                    players_metadata.iter().find_map(|m| {
-                        if m.id == slot.player_id && !m.name.is_empty() {
-                            Some(Player {
-                                team_id: slot.team_id,
-                                id: m.id,
-                                name: m.name.clone(),
-                                race: slot.race.clone(),
-                                color: slot.color,
-                                host: false,
+    pub fn try_parse<P: AsRef<Path>>(file: P) -> Result<Game, W3rsError> {
+        Game::from_bytes(&fs::read(file)?)
+    }
+
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Game, W3rsError> {
+        let mut file = Vec::new();
+        reader.read_to_end(&mut file)?;
+        Game::from_bytes(&file)
+    }
+
+    pub fn from_bytes(file: &[u8]) -> Result<Game, W3rsError> {
+        Game::decode(file, false)
+    }
+
+    /// For replays cut short by crashes or disconnects: decodes every intact data block
+    /// and parses game blocks up to the first failure, see `truncated_at` and `failures`.
+    /// Fails only if the header or the game metadata can't be read
+    pub fn from_bytes_lenient(file: &[u8]) -> Result<Game, W3rsError> {
+        Game::decode(file, true)
+    }
+
+    /// Fails on the first action that can't be decoded,
+    /// where `from_bytes` skips the rest of the command it belongs to
+    pub fn from_bytes_strict(file: &[u8]) -> Result<Game, W3rsError> {
+        let game = Game::from_bytes(file)?;
+        match game.failed_action() {
+            Some(e) => Err(e),
+            None => Ok(game),
+        }
+    }
+
+    fn failed_action(&self) -> Option<W3rsError> {
+        let mut time = Duration::from_millis(0);
+        for block in &self.blocks {
+            if let GameBlock::TimeSlot(ts) = block {
+                time += Duration::from_millis(ts.time_increment as u64);
+                for cmd in &ts.commands {
+                    let player = cmd.player;
+                    match cmd.failed_action() {
+                        Some(ParsedAction::Unknown(kind)) => {
+                            return Some(W3rsError::UnknownAction {
+                                kind: *kind,
+                                player,
+                                time,
                             })
-                        } else {
-                            None
                         }
-                    })
+                        Some(ParsedAction::Invalid(kind)) => {
+                            return Some(W3rsError::InvalidAction {
+                                kind: *kind,
+                                player,
+                                time,
+                            })
+                        }
+                        _ => {}
+                    }
                 }
-            })
-            .collect();
-        Game {
+            }
+        }
+        None
+    }
+
+    fn decode(file: &[u8], lenient: bool) -> Result<Game, W3rsError> {
+        let mut failures = Vec::new();
+        let (rest, file_metadata) =
+            parse_header(file).map_err(failed_at(ParseStage::Header, file))?;
+        let header = ReplayHeader::from(&file_metadata);
+        let version = header.game_version();
+        let (rest, blocks) = compressed_data_blocks(version)(rest)
+            .map_err(failed_at(ParseStage::CompressedDataBlocks, file))?;
+        if blocks.len() < file_metadata.compressed_data_block_count as usize {
+            if let Err(e) = compressed_data_block(rest, version) {
+                let failure = failed_at(ParseStage::CompressedDataBlocks, file)(e);
+                recover(failure, lenient, &mut failures)?;
+            }
+        }
+        let decoded = if lenient {
+            let (decoded, failure) = deflate_intact_blocks(&blocks);
+            failures.extend(failure);
+            decoded
+        } else {
+            deflate_game(&blocks)?
+        };
+        // blocks are padded with zeros
+        let complete = decoded.len() >= header.decompressed_size as usize;
+        let decoded = &decoded[..decoded.len().min(header.decompressed_size as usize)];
+        let (rest, setup) = parse_setup(decoded, version)?;
+        let (settings, map) = setup.map_info()?;
+        let (blocks, failure) = parse_game_blocks(rest, version);
+        if let Some(e) = failure {
+            let failure = failed_at(ParseStage::GameBlocks, decoded)(e);
+            recover(failure, lenient, &mut failures)?;
+        }
+        let truncated_at = if complete && failures.is_empty() {
+            None
+        } else {
+            Some(game_time(&blocks))
+        };
+        let players = setup.players();
+        let slots = setup.slots.iter().map(Slot::from).collect();
+        let Setup {
+            metadata,
+            players_metadata,
+            reforged_records,
+            game_start_record,
+            slots: slot_records,
+            pos,
+            ..
+        } = setup;
+        Ok(Game {
+            header,
             name: metadata.game_name.clone(),
-            game_type: metadata.game_type(),
+            flags: GameFlags::from(metadata.game_type),
+            language: Language::from(metadata.language),
             players,
+            slots,
             game_start_record,
-            pos: game_pos_data,
+            pos,
             map,
+            settings,
             blocks,
+            metadata,
+            players_metadata,
+            reforged_records,
+            slot_records,
+            truncated_at,
+            failures,
+        })
+    }
+
+    /// Serializes the game back into a `.w3g` replay
+    pub fn to_bytes(&self) -> Result<Vec<u8>, W3rsError> {
+        let version = self.header.game_version();
+        let mut data = Vec::with_capacity(self.header.decompressed_size as usize);
+        write_game_metadata(&mut data, &self.metadata);
+        write_players(&mut data, &self.players_metadata);
+        write_players_reforged(&mut data, &self.reforged_records);
+        write_start_record(&mut data, &self.game_start_record);
+        write_players_slots(&mut data, &self.slot_records, version);
+        write_game_pos(&mut data, &self.pos);
+        write_game_blocks(&mut data, &self.blocks);
+        write_replay(&self.header, &data)
+    }
+
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<(), W3rsError> {
+        writer.write_all(&self.to_bytes()?)?;
+        Ok(())
+    }
+
+    /// Cuts the game at `at` (game time): later blocks are dropped and the players still in game leave.
+    /// Only the end of a replay can be cut, the game needs every command from the start to simulate it
+    pub fn truncate(&mut self, at: Duration) {
+        let mut time = Duration::from_millis(0);
+        let cut = self.blocks.iter().position(|block| match block {
+            GameBlock::TimeSlot(ts) => {
+                time += Duration::from_millis(ts.time_increment as u64);
+                time > at
+            }
+            _ => false,
+        });
+        let cut = match cut {
+            Some(cut) => cut,
+            None => return,
+        };
+        // the player who saved the replay leaves last
+        let saver = self
+            .leave_blocks()
+            .iter()
+            .find(|l| l.reason[0] == LEAVE_LOCAL_GAME)
+            .map(|l| l.player_id)
+            .or_else(|| {
+                self.players
+                    .iter()
+                    .rev()
+                    .find(|p| !p.is_computer())
+                    .map(|p| p.id)
+            });
+        self.blocks.truncate(cut);
+        let left: Vec<u8> = self.leave_blocks().iter().map(|l| l.player_id).collect();
+        let unknown = self
+            .leave_blocks()
+            .last()
+            .map(|l| l.unknown)
+            .unwrap_or([1, 0, 0, 0]);
+        let mut leaving: Vec<u8> = self
+            .players
+            .iter()
+            .filter(|p| !p.is_computer())
+            .map(|p| p.id)
+            .filter(|id| !left.contains(id))
+            .unique()
+            .collect();
+        leaving.sort_by_key(|id| Some(*id) == saver);
+        for player_id in leaving {
+            let reason = if Some(player_id) == saver {
+                LEAVE_LOCAL_GAME
+            } else {
+                LEAVE_REMOTE_GAME
+            };
+            self.blocks.push(GameBlock::Leave(LeaveGameBlock {
+                player_id,
+                reason: [reason, 0, 0, 0],
+                result: [LEAVE_RESULT_LEFT, 0, 0, 0],
+                unknown,
+            }));
         }
+        self.header.duration = game_time(&self.blocks);
     }
 
     pub fn players_by_team(&self) -> Vec<(u16, Vec<&Player>)> {
@@ -197,12 +409,7 @@ impl Game {
     }
 
     pub fn game_type(&self) -> (GameContext, GameType) {
-        let context = match self.game_type[0].as_str() {
-            "09" => GameContext::Custom,
-            "01" => GameContext::Ladder,
-            "1d" => GameContext::SinglePlayer,
-            str => GameContext::Unknown(str.to_string()),
-        };
+        let context = self.flags.context.clone();
         let by_team = self.players_by_team();
         let typ = if by_team.len() > 2 && by_team.iter().all(|(_, p)| p.len() == 1) {
             GameType::FFA
@@ -220,110 +427,519 @@ impl Game {
         (context, typ)
     }
 
+    /// Resources each player sent to and received from allies, by player id
+    pub fn resource_transfers(&self) -> HashMap<u8, ResourceTransfers> {
+        let mut transfers: HashMap<u8, ResourceTransfers> = HashMap::new();
+        for event in self.events() {
+            if let (
+                Some(player_id),
+                Event::Action {
+                    action:
+                        Action::TransferResources {
+                            to_player,
+                            gold,
+                            lumber,
+                        },
+                    ..
+                },
+            ) = (event.player_id, event.event)
+            {
+                let sender = transfers.entry(player_id).or_default();
+                sender.gold_sent += gold as u64;
+                sender.lumber_sent += lumber as u64;
+                let receiver = transfers.entry(to_player).or_default();
+                receiver.gold_received += gold as u64;
+                receiver.lumber_received += lumber as u64;
+            }
+        }
+        transfers
+    }
+
+    pub fn pauses(&self) -> Vec<Pause> {
+        let mut pauses: Vec<Pause> = Vec::new();
+        let mut paused = false;
+        for event in self.events() {
+            match (event.player_id, event.event) {
+                (Some(player_id), Event::Paused) if !paused => {
+                    paused = true;
+                    pauses.push(Pause {
+                        paused_by: player_id,
+                        at: event.time,
+                        game_time: event.game_time,
+                        resumed_by: None,
+                        duration: game_time(&self.blocks) - event.time,
+                    });
+                }
+                (resumed_by, Event::Resumed { paused_for }) if paused => {
+                    paused = false;
+                    if let Some(pause) = pauses.last_mut() {
+                        pause.resumed_by = resumed_by;
+                        pause.duration = paused_for;
+                    }
+                }
+                _ => {}
+            }
+        }
+        pauses
+    }
+
     pub fn events(&self) -> Vec<GameEvent> {
-        let mut time = Duration::from_millis(0);
-        let mut game_components: HashMap<u32, GameComponent> = HashMap::new();
-        let mut player_selection: HashMap<u8, Vec<SelectedComponent>> = HashMap::new();
+        let mut tracker = EventTracker::new(&self.slot_records);
         let mut events: Vec<GameEvent> = Vec::new();
-        let mut player_hotkey_groups: HashMap<u8, Vec<SelectedComponent>> = HashMap::new();
         for block in &self.blocks {
-            match block {
-                GameBlock::TimeSlot(ts_block) => {
-                    time += Duration::from_millis(ts_block.time_increment as u64);
-                    if let Some(cmd) = &ts_block.command {
-                        let player = cmd.player;
-                        let actions = &cmd.actions;
-                        for action in actions {
-                            // Update selection
-                            if let Some(selection) = action.selection(&mut player_hotkey_groups) {
-                                for selected in selection.clone() {
-                                    if let Some(component) = selected.kind {
-                                        game_components.insert(selected.id_1, component.clone());
-                                        game_components.insert(selected.id_2, component.clone());
-                                    }
-                                }
-                                if selection.is_empty() {
-                                    player_selection.remove(&player);
-                                } else {
-                                    let enhanced_selection: Vec<SelectedComponent> = selection
-                                        .iter()
-                                        .map(|comp| SelectedComponent {
-                                            id_1: comp.id_1,
-                                            id_2: comp.id_2,
-                                            kind: game_components
-                                                .get(&comp.id_1)
-                                                .or_else(|| game_components.get(&comp.id_2))
-                                                .map(GameComponent::clone),
-                                        })
-                                        .collect();
-                                    player_selection.insert(player, enhanced_selection);
+            tracker.track(block, &mut events);
+        }
+        events
+    }
+}
+
+/// Everything the game data holds before the game blocks
+pub(crate) struct Setup {
+    pub metadata: GameMetaData,
+    pub players_metadata: Vec<PlayerMetaData>,
+    pub reforged_records: Vec<ReforgedRecord>,
+    pub players_reforged_metadata: Vec<ReforgedPlayerMetaData>,
+    pub game_start_record: GameStartRecord,
+    pub slots: Vec<PlayerSlotMetaData>,
+    pub pos: GamePosData,
+}
+
+/// Parses the start of the decompressed game data, error offsets are relative to `decoded`
+pub(crate) fn parse_setup(
+    decoded: &[u8],
+    version: GameVersion,
+) -> Result<(&[u8], Setup), W3rsError> {
+    let (rest, metadata) =
+        parse_game_metadata(decoded).map_err(failed_at(ParseStage::GameMetadata, decoded))?;
+    let (rest, players_metadata) =
+        parse_players(rest).map_err(failed_at(ParseStage::Players, decoded))?;
+    let mut rest = rest;
+    let mut reforged_records = Vec::new();
+    let mut players_reforged_metadata = Vec::new();
+    if rest.first() != Some(&0x19) {
+        let (b, (records, reforged)) = parse_players_reforged(rest)
+            .map_err(failed_at(ParseStage::ReforgedPlayers, decoded))?;
+        reforged_records = records;
+        players_reforged_metadata = reforged;
+        rest = b;
+    }
+    let (rest, game_start_record) =
+        parse_start_record(rest).map_err(failed_at(ParseStage::StartRecord, decoded))?;
+    let (rest, slots) = parse_players_slots(game_start_record.slot_record_count, version)(rest)
+        .map_err(failed_at(ParseStage::PlayerSlots, decoded))?;
+    let (rest, pos) = parse_game_pos(rest).map_err(failed_at(ParseStage::GamePos, decoded))?;
+    Ok((
+        rest,
+        Setup {
+            metadata,
+            players_metadata,
+            reforged_records,
+            players_reforged_metadata,
+            game_start_record,
+            slots,
+            pos,
+        },
+    ))
+}
+
+impl Setup {
+    pub fn map_info(&self) -> Result<(GameSettings, MapInfo), W3rsError> {
+        let map_info = crate::utils::decode(&self.metadata.encoded_map_info);
+        let (_, map_info) =
+            parse_map_info(&map_info[..]).map_err(failed_at(ParseStage::MapInfo, &map_info))?;
+        Ok(map_info)
+    }
+
+    pub fn players(&self) -> Vec<Player> {
+        let host = &self.metadata.host;
+        let mut players: Vec<Player> = self
+            .slots
+            .iter()
+            .flat_map(|slot| {
+                let computer = Slot::from(slot).computer;
+                if let Some(difficulty) = computer.filter(|_| slot.slot_status == 2) {
+                    Some(Player {
+                        team_id: slot.team_id,
+                        id: slot.player_id,
+                        name: computer_name(difficulty),
+                        race: slot.race.clone(),
+                        color: slot.color,
+                        host: false,
+                        clan: None,
+                        portrait: None,
+                        computer,
+                        handicap: slot.handicap_flag,
+                    })
+                } else if host.id == slot.player_id {
+                    Some(Player {
+                        team_id: slot.team_id,
+                        id: host.id,
+                        name: host.name(),
+                        race: slot.race.clone(),
+                        color: slot.color,
+                        host: true,
+                        clan: None,
+                        portrait: None,
+                        computer: None,
+                        handicap: slot.handicap_flag,
+                    })
+                } else {
+                    self.players_metadata.iter().find_map(|m| {
+                        if m.id == slot.player_id && !m.name.is_empty() {
+                            Some(Player {
+                                team_id: slot.team_id,
+                                id: m.id,
+                                name: m.name(),
+                                race: slot.race.clone(),
+                                color: slot.color,
+                                host: false,
+                                clan: None,
+                                portrait: None,
+                                computer: None,
+                                handicap: slot.handicap_flag,
+                            })
+                        } else {
+                            None
+                        }
+                    })
+                }
+            })
+            .collect();
+        for player in players.iter_mut().filter(|p| !p.is_computer()) {
+            if let Some(reforged) = self
+                .players_reforged_metadata
+                .iter()
+                .find(|r| r.id == player.id as u32)
+            {
+                player.merge_reforged_metadata(reforged);
+            }
+        }
+        players
+    }
+}
+
+/// As the game names them in the lobby
+fn computer_name(difficulty: AiDifficulty) -> String {
+    match difficulty {
+        AiDifficulty::Easy => "Computer (Easy)".to_string(),
+        AiDifficulty::Normal => "Computer (Normal)".to_string(),
+        AiDifficulty::Insane => "Computer (Insane)".to_string(),
+        AiDifficulty::Unknown(strength) => format!("Computer ({})", strength),
+    }
+}
+
+fn trigger_event<'a>(action: &'a ParsedAction, trigger_chat: &mut Vec<&'a str>) -> Option<Event> {
+    match action {
+        ParsedAction::MapTriggerChat(chat) if !trigger_chat.contains(&chat.text.as_str()) => {
+            trigger_chat.push(&chat.text);
+            Some(Event::TriggerChat {
+                text: chat.text.clone(),
+            })
+        }
+        ParsedAction::ScenarioTrigger(trigger) => Some(Event::ScenarioTrigger {
+            trigger_1: trigger.trigger_1,
+            trigger_2: trigger.trigger_2,
+            counter: trigger.counter,
+        }),
+        _ => None,
+    }
+}
+
+/// Turns game blocks into events, one block at a time
+#[derive(Default)]
+pub(crate) struct EventTracker {
+    time: Duration,
+    /// Time spent paused, up to the last resume
+    paused: Duration,
+    paused_since: Option<Duration>,
+    game_components: HashMap<u32, GameComponent>,
+    player_selection: HashMap<u8, Vec<SelectedComponent>>,
+    player_hotkey_groups: HashMap<u8, Vec<SelectedComponent>>,
+    /// Player ids by slot, resource transfers target slots
+    slot_players: Vec<u8>,
+}
+
+impl EventTracker {
+    pub fn new(slots: &[PlayerSlotMetaData]) -> Self {
+        EventTracker {
+            slot_players: slots.iter().map(|slot| slot.player_id).collect(),
+            ..EventTracker::default()
+        }
+    }
+
+    fn game_time(&self) -> Duration {
+        let pausing = self
+            .paused_since
+            .map_or(Duration::from_millis(0), |since| self.time - since);
+        self.time - self.paused - pausing
+    }
+
+    fn event(&self, player_id: u8, event: Event) -> GameEvent {
+        GameEvent {
+            player_id: Some(player_id),
+            ..self.game_event(event)
+        }
+    }
+
+    fn game_event(&self, event: Event) -> GameEvent {
+        GameEvent {
+            player_id: None,
+            time: self.time,
+            game_time: self.game_time(),
+            event,
+        }
+    }
+
+    /// Pauses, game speed changes and saves
+    fn timeline_event(&mut self, action: &ParsedAction) -> Option<Event> {
+        match action {
+            ParsedAction::Pause => {
+                self.paused_since.get_or_insert(self.time);
+                Some(Event::Paused)
+            }
+            ParsedAction::Resume => {
+                let paused_for = self
+                    .paused_since
+                    .take()
+                    .map_or(Duration::from_millis(0), |since| self.time - since);
+                self.paused += paused_for;
+                Some(Event::Resumed { paused_for })
+            }
+            ParsedAction::SetSpeed(speed) => Some(Event::SpeedChanged {
+                speed: GameSpeed::from(*speed),
+            }),
+            ParsedAction::IncreaseGameSpeed => Some(Event::SpeedIncreased),
+            ParsedAction::DecreaseGameSpeed => Some(Event::SpeedDecreased),
+            ParsedAction::Save(name) => Some(Event::Saving { name: name.clone() }),
+            ParsedAction::SaveFinished => Some(Event::Saved),
+            _ => None,
+        }
+    }
+
+    /// Actions targeting another player's slot, which don't depend on the selection
+    fn towards_player(&self, action: &ParsedAction) -> Option<Action> {
+        let player = |slot: u8| self.slot_players.get(slot as usize).copied();
+        match action {
+            ParsedAction::TransferResources(transfer) => {
+                player(transfer.player_slot).map(|to_player| Action::TransferResources {
+                    to_player,
+                    gold: transfer.gold,
+                    lumber: transfer.lumber,
+                })
+            }
+            ParsedAction::ChangeAllyOptions(alliance) => {
+                player(alliance.player_slot).map(|with_player| Action::ChangeAlliance {
+                    with_player,
+                    options: alliance.options,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn track(&mut self, block: &GameBlock, events: &mut Vec<GameEvent>) {
+        match block {
+            GameBlock::TimeSlot(ts_block) => {
+                self.time += Duration::from_millis(ts_block.time_increment as u64);
+                for cmd in &ts_block.commands {
+                    let player = cmd.player;
+                    let actions = &cmd.actions;
+                    // every trigger listening to chat receives the text
+                    let mut trigger_chat: Vec<&str> = Vec::new();
+                    for action in actions {
+                        if let Some(event) = trigger_event(action, &mut trigger_chat)
+                            .or_else(|| self.timeline_event(action))
+                        {
+                            events.push(self.event(player, event));
+                            continue;
+                        }
+                        if matches!(
+                            action,
+                            ParsedAction::TransferResources(_) | ParsedAction::ChangeAllyOptions(_)
+                        ) {
+                            if let Some(action) = self.towards_player(action) {
+                                let event = Event::Action {
+                                    selection: Vec::new(),
+                                    action,
+                                    flags: None,
+                                };
+                                events.push(self.event(player, event));
+                            }
+                            continue;
+                        }
+                        // Update selection
+                        if let Some(selection) = action.selection(&mut self.player_hotkey_groups) {
+                            for selected in selection.clone() {
+                                if let Some(component) = selected.kind {
+                                    self.game_components
+                                        .insert(selected.id_1, component.clone());
+                                    self.game_components
+                                        .insert(selected.id_2, component.clone());
                                 }
                             }
-                            if let Some(selected_units) = player_selection.get(&player) {
-                                let parsed =
-                                    from_parsed_action(selected_units, action, &game_components);
-                                if let Some(action) = parsed {
-                                    events.push(GameEvent {
-                                        time,
-                                        player_id: player,
-                                        event: Event::Action {
-                                            selection: selected_units
-                                                .iter()
-                                                .flat_map(|s| s.kind.as_ref())
-                                                .map(GameComponent::clone)
-                                                .collect(),
-                                            action,
-                                        },
+                            if selection.is_empty() {
+                                self.player_selection.remove(&player);
+                            } else {
+                                let enhanced_selection: Vec<SelectedComponent> = selection
+                                    .iter()
+                                    .map(|comp| SelectedComponent {
+                                        id_1: comp.id_1,
+                                        id_2: comp.id_2,
+                                        kind: self
+                                            .game_components
+                                            .get(&comp.id_1)
+                                            .or_else(|| self.game_components.get(&comp.id_2))
+                                            .cloned(),
                                     })
-                                }
+                                    .collect();
+                                self.player_selection.insert(player, enhanced_selection);
+                            }
+                        }
+                        if let Some(selected_units) = self.player_selection.get(&player) {
+                            let parsed =
+                                from_parsed_action(selected_units, action, &self.game_components);
+                            let flags = action.ability_flags();
+                            if let Some(action) = parsed {
+                                let event = Event::Action {
+                                    selection: selected_units
+                                        .iter()
+                                        .flat_map(|s| s.kind.as_ref())
+                                        .map(GameComponent::clone)
+                                        .collect(),
+                                    action,
+                                    flags,
+                                };
+                                events.push(self.event(player, event))
                             }
                         }
                     }
                 }
-                GameBlock::PlayerChatMsg(msg) => {
-                    if let ChatMsgBlock::Msg(addressee) = &msg.kind {
-                        events.push(GameEvent {
-                            player_id: msg.player_id,
-                            time,
-                            event: Event::ChatMsg {
-                                addressee: addressee.clone(),
-                                message: msg.text.clone(),
-                            },
-                        })
-                    }
+            }
+            GameBlock::PlayerChatMsg(msg) => {
+                if let ChatMsgBlock::Msg(mode) = msg.kind {
+                    let event = Event::ChatMsg {
+                        addressee: Addressee::from_mode(mode),
+                        message: msg.text.clone(),
+                    };
+                    events.push(self.event(msg.player_id, event))
                 }
-                _ => {}
             }
+            GameBlock::Leave(leave) => {
+                let event = Event::PlayerLeft {
+                    reason: leave.leave_reason(),
+                    result: leave.leave_result(),
+                };
+                events.push(self.event(leave.player_id, event))
+            }
+            GameBlock::GameStart(start) if start.kind == 0x1A => {
+                events.push(self.game_event(Event::GameStarted))
+            }
+            GameBlock::Checksum(block) => {
+                if let Some(checksum) = block.checksum() {
+                    events.push(self.game_event(Event::Checksum { checksum }))
+                }
+            }
+            GameBlock::Unknown023(data) => {
+                events.push(self.game_event(Event::Unknown023 { data: *data }))
+            }
+            GameBlock::GameEndCountdown(countdown) => {
+                let event = Event::GameEndCountdown {
+                    over: countdown.is_over(),
+                    countdown: Duration::from_secs(countdown.countdown_secs as u64),
+                };
+                events.push(self.game_event(event))
+            }
+            _ => {}
         }
-        events
     }
 }
 
+/// Compresses decompressed game data into a replay file, with a header matching `header`
+pub(crate) fn write_replay(header: &ReplayHeader, data: &[u8]) -> Result<Vec<u8>, W3rsError> {
+    let version = header.game_version();
+    let blocks = compress_game(data, version)?;
+    let mut compressed = Vec::new();
+    write_compressed_data_blocks(&mut compressed, &blocks, version);
+    let header_version = header.header_version();
+    let offset = FileMetaData::header_size(header_version);
+    let file_metadata = FileMetaData {
+        offset,
+        compressed_size: offset + compressed.len() as u32,
+        header_version,
+        decompressed_size: data.len() as u32,
+        compressed_data_block_count: blocks.len() as u32,
+        replay_metadata: ReplayMetaData {
+            game_identifier: header.game_identifier().to_string(),
+            version: header.version,
+            build_no: header.build_no,
+            flags: header.flags,
+            replay_length_ms: header.duration.as_millis() as u32,
+            checksum: 0, // computed by write_header
+        },
+    };
+    let mut file = Vec::with_capacity(file_metadata.compressed_size as usize);
+    write_header(&mut file, &file_metadata);
+    file.extend(compressed);
+    Ok(file)
+}
+
+/// Keeps track of the failure in lenient mode, fails otherwise
+fn recover(
+    failure: W3rsError,
+    lenient: bool,
+    failures: &mut Vec<W3rsError>,
+) -> Result<(), W3rsError> {
+    if lenient {
+        failures.push(failure);
+        Ok(())
+    } else {
+        Err(failure)
+    }
+}
+
+fn game_time(blocks: &[GameBlock]) -> Duration {
+    blocks
+        .iter()
+        .fold(Duration::from_millis(0), |time, block| match block {
+            GameBlock::TimeSlot(ts) => time + Duration::from_millis(ts.time_increment as u64),
+            _ => time,
+        })
+}
+
 pub(crate) fn non_noisy(event: &&GameEvent) -> bool {
     match &event.event {
         // avoid noisy actions
-        Event::Action {
-            action,
-            selection: _,
-        } => !matches!(
+        Event::Action { action, .. } => !matches!(
             action,
             Action::Move(_)
                 | Action::SetRallyPoint(_)
                 | Action::RightClick { at: _, target: _ }
                 | Action::Attack { at: _, target: _ }
         ),
+        Event::Checksum { .. } => false,
         _ => true,
     }
 }
 
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
+    use crate::action::{AbilityFlags, Action};
+    use crate::blocks::compressedblock::{compressed_data_blocks, deflate_game};
+    use crate::blocks::gameblock::GameBlock;
     use crate::display::player::player_msg_color;
-    use crate::event::{Event, GameEvent};
-    use crate::game::{non_noisy, Game};
-    use crate::tests::ignored_replay;
+    use crate::error::{ParseStage, W3rsError};
+    use crate::event::{Event, GameEvent, LeaveReason, LeaveResult};
+    use crate::game::{non_noisy, Game, GameOutcome, LEAVE_LOCAL_GAME, LEAVE_REMOTE_GAME};
+    use crate::metadata::replay::{parse_header, ReplayHeader};
+    use crate::player::{AiDifficulty, SlotStatus};
+    use crate::tests::{
+        crate_root, ignored_replay, replay, replay_bytes, replays_dir, with_computers,
+    };
     use colored::{Color, Colorize};
     use humantime::format_duration;
+    use std::ffi::OsStr;
+    use std::fs;
+    use std::time::Duration;
 
     #[test]
     fn parse_replay_events() {
@@ -332,25 +948,429 @@ mod tests {
         println!("{}", game);
         let events = game.events();
         for event in events.iter().filter(non_noisy).collect::<Vec<&GameEvent>>() {
-            let color = game
-                .player(event.player_id)
+            let color = event
+                .player_id
+                .and_then(|id| game.player(id))
                 .and_then(player_msg_color)
                 .unwrap_or(Color::White);
+            let source = match event.player_id {
+                Some(id) => format!("Player {}", id),
+                None => "Game".to_string(),
+            };
             print!(
                 "{}",
-                format!(
-                    "[{}] Player {}: ",
-                    format_duration(event.time),
-                    event.player_id
-                )
-                .color(color)
+                format!("[{}] {}: ", format_duration(event.time), source).color(color)
             );
             match &event.event {
                 Event::ChatMsg { addressee, message } => println!("{} {}", addressee, message),
-                Event::Action { selection, action } => {
+                Event::Action {
+                    selection, action, ..
+                } => {
                     println!("{}", format!("{:?} {}", selection, action).color(color));
                 }
+                other => println!("{:?}", other),
             };
         }
     }
+
+    #[test]
+    fn not_a_replay() {
+        let mut path = crate_root();
+        path.push("Cargo.toml");
+        let err = Game::try_parse(path).unwrap_err();
+        assert!(matches!(
+            err,
+            W3rsError::Parse {
+                stage: ParseStage::Header,
+                offset: 0,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn truncated_replay_does_not_panic() {
+        let bytes = replay_bytes("reforged1.w3g");
+        let mut path = std::env::temp_dir();
+        path.push("w3rs_truncated_replay.w3g");
+        fs::write(&path, &bytes[..100]).unwrap();
+        let res = Game::try_parse(&path);
+        fs::remove_file(&path).unwrap();
+        assert!(res.is_err());
+        assert!(Game::from_bytes(&bytes[..100]).is_err());
+    }
+
+    pub(crate) fn decompressed(file: &[u8]) -> (ReplayHeader, Vec<u8>) {
+        let (rest, metadata) = parse_header(file).unwrap();
+        let header = ReplayHeader::from(&metadata);
+        let (rest, blocks) = compressed_data_blocks(header.game_version())(rest).unwrap();
+        assert!(rest.is_empty());
+        (header, deflate_game(&blocks).unwrap())
+    }
+
+    #[test]
+    fn write_replays_back() {
+        for path in fs::read_dir(replays_dir())
+            .unwrap()
+            .map(|f| f.unwrap().path())
+            .filter(|f| f.extension() == Some(OsStr::new("w3g")))
+        {
+            let bytes = fs::read(&path).unwrap();
+            let game = Game::from_bytes(&bytes).unwrap();
+            let written = game.to_bytes().unwrap();
+            // flate2 doesn't compress exactly like the game does: compare decompressed data
+            let (header, data) = decompressed(&bytes);
+            let (written_header, written_data) = decompressed(&written);
+            assert_eq!(header, written_header, "{:?}", path);
+            assert!(data == written_data, "{:?} data differs", path);
+            let rewritten = Game::from_bytes(&written).unwrap();
+            assert_eq!(game.players, rewritten.players);
+            assert_eq!(game.events(), rewritten.events());
+        }
+    }
+
+    #[test]
+    fn truncate_replay() {
+        let mut game = Game::from_bytes(&replay_bytes("reforged2010.w3g")).unwrap();
+        let full = game.events();
+        let at = Duration::from_secs(120);
+        game.truncate(at);
+        let cut = Game::from_bytes(&game.to_bytes().unwrap()).unwrap();
+        assert!(cut.header.duration <= at);
+        assert!(cut.header.duration > at - Duration::from_secs(1));
+        // the players still in game leave at the end
+        let (left, events): (Vec<GameEvent>, Vec<GameEvent>) = cut
+            .events()
+            .into_iter()
+            .partition(|e| matches!(e.event, Event::PlayerLeft { .. }));
+        assert_eq!(full.iter().filter(|e| e.time <= at).count(), events.len());
+        assert_eq!(&full[..events.len()], &events[..]);
+        assert_eq!(cut.players.len(), left.len());
+        let last = left.last().unwrap();
+        assert_eq!(Some(3), last.player_id); // saved the replay
+        assert_eq!(
+            Event::PlayerLeft {
+                reason: LeaveReason::LocalGame,
+                result: LeaveResult::Left
+            },
+            last.event
+        );
+    }
+
+    #[test]
+    fn player_left() {
+        let game = Game::from_bytes(&replay_bytes("reforged2010.w3g")).unwrap();
+        let left: Vec<(u8, LeaveResult)> = game
+            .events()
+            .into_iter()
+            .filter_map(|e| match e.event {
+                Event::PlayerLeft { result, .. } => e.player_id.map(|id| (id, result)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(game.leave_blocks().len(), left.len());
+        // the player who saved the replay leaves last, before the game is over
+        assert_eq!((3, LeaveResult::Left), *left.last().unwrap());
+        assert!(left[..left.len() - 1]
+            .iter()
+            .all(|(_, result)| *result == LeaveResult::Lost));
+    }
+
+    #[test]
+    fn lenient_truncated_replay() {
+        let bytes = replay_bytes("reforged2010.w3g");
+        let complete = Game::from_bytes_lenient(&bytes).unwrap();
+        assert_eq!(None, complete.truncated_at);
+        assert!(complete.failures.is_empty());
+
+        let truncated = &bytes[..bytes.len() / 2];
+        assert!(matches!(
+            Game::from_bytes(truncated),
+            Err(W3rsError::Parse {
+                stage: ParseStage::CompressedDataBlocks,
+                ..
+            })
+        ));
+        let game = Game::from_bytes_lenient(truncated).unwrap();
+        let truncated_at = game.truncated_at.unwrap();
+        assert!(truncated_at > Duration::from_secs(60));
+        assert!(truncated_at < complete.header.duration);
+        assert_eq!(
+            ParseStage::CompressedDataBlocks,
+            game.failures[0].stage().unwrap()
+        );
+        assert_eq!(complete.players, game.players);
+        let events = game.events();
+        assert!(!events.is_empty());
+        assert_eq!(&complete.events()[..events.len()], &events[..]);
+    }
+
+    #[test]
+    fn strict_unknown_action() {
+        let bytes = replay_bytes("reforged2010.w3g");
+        assert!(Game::from_bytes_strict(&bytes).is_ok());
+        let mut game = Game::from_bytes(&bytes).unwrap();
+        let cmd = game
+            .blocks
+            .iter_mut()
+            .filter_map(|block| match block {
+                GameBlock::TimeSlot(ts) => ts.commands.first_mut(),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        cmd.data[0] = 0xFF;
+        let player = cmd.player;
+        let bytes = game.to_bytes().unwrap();
+        assert!(Game::from_bytes(&bytes).is_ok());
+        match Game::from_bytes_strict(&bytes) {
+            Err(W3rsError::UnknownAction {
+                kind, player: p, ..
+            }) => {
+                assert_eq!(0xFF, kind);
+                assert_eq!(player, p);
+            }
+            other => panic!("expected an unknown action, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn strict_invalid_action() {
+        let mut game = Game::from_bytes(&replay_bytes("reforged2010.w3g")).unwrap();
+        let cmd = game
+            .blocks
+            .iter_mut()
+            .filter_map(|block| match block {
+                GameBlock::TimeSlot(ts) => ts.commands.first_mut(),
+                _ => None,
+            })
+            .next()
+            .unwrap();
+        // an ability without its payload
+        cmd.data = vec![0x10, 0x00];
+        let bytes = game.to_bytes().unwrap();
+        assert!(Game::from_bytes(&bytes).is_ok());
+        match Game::from_bytes_strict(&bytes) {
+            Err(W3rsError::InvalidAction { kind, .. }) => assert_eq!(0x10, kind),
+            other => panic!("expected an invalid action, got {:?}", other.err()),
+        }
+    }
+
+    #[test]
+    fn ability_flags() {
+        let game = Game::from_bytes(&replay_bytes("reforged2010.w3g")).unwrap();
+        let actions: Vec<(Action, Option<AbilityFlags>)> = game
+            .events()
+            .into_iter()
+            .filter_map(|e| match e.event {
+                Event::Action { action, flags, .. } => Some((action, flags)),
+                _ => None,
+            })
+            .collect();
+        // no flags for actions that aren't abilities
+        assert!(actions
+            .iter()
+            .filter(|(action, _)| matches!(
+                action,
+                Action::TransferResources { .. } | Action::ChangeAlliance { .. }
+            ))
+            .all(|(_, flags)| flags.is_none()));
+        let flags: Vec<AbilityFlags> = actions.into_iter().filter_map(|(_, f)| f).collect();
+        let queued = flags.iter().filter(|f| f.queued).count();
+        let command_card = flags.iter().filter(|f| f.command_card).count();
+        assert!(queued > 0);
+        // hotkeys and clicks on the command card, but also right clicks
+        assert!(command_card > 0 && command_card < flags.len());
+    }
+
+    #[test]
+    fn resource_transfers() {
+        let game = Game::from_bytes(&replay_bytes("reforged2010.w3g")).unwrap();
+        let transfers: Vec<(u8, Action)> = game
+            .events()
+            .into_iter()
+            .filter_map(|e| match e.event {
+                Event::Action { action, .. } => e.player_id.map(|id| (id, action)),
+                _ => None,
+            })
+            .filter(|(_, a)| matches!(a, Action::TransferResources { .. }))
+            .collect();
+        assert_eq!(9, transfers.len());
+        assert_eq!(
+            (
+                4,
+                Action::TransferResources {
+                    to_player: 5,
+                    gold: 0,
+                    lumber: 100
+                }
+            ),
+            transfers[0]
+        );
+        let summary = game.resource_transfers();
+        assert_eq!(5, summary.len());
+        assert_eq!(6400, summary[&5].net_gold());
+        assert_eq!(-324, summary[&5].net_lumber());
+        assert_eq!(-6400, summary[&3].net_gold());
+        assert_eq!(1324, summary[&3].net_lumber());
+        assert_eq!(500, summary[&6].gold_received);
+    }
+
+    #[test]
+    fn huge_resource_transfers() {
+        let mut game = Game::from_bytes(&replay_bytes("reforged2010.w3g")).unwrap();
+        let mut count = 0;
+        for block in game.blocks.iter_mut() {
+            if let GameBlock::TimeSlot(ts) = block {
+                for cmd in ts.commands.iter_mut() {
+                    // a single transfer: kind, slot, gold, lumber
+                    if cmd.data.len() == 10 && cmd.data[0] == 0x51 {
+                        cmd.data[2..6].copy_from_slice(&u32::MAX.to_le_bytes());
+                        count += 1;
+                    }
+                }
+            }
+        }
+        assert!(count > 1);
+        let game = Game::from_bytes(&game.to_bytes().unwrap()).unwrap();
+        let sent: u64 = game
+            .resource_transfers()
+            .values()
+            .map(|t| t.gold_sent)
+            .sum();
+        assert_eq!(count * u32::MAX as u64, sent);
+    }
+
+    #[test]
+    fn trigger_chat() {
+        let game = Game::from_bytes(&replay_bytes("reforged_metadata_ghostplayer.w3g")).unwrap();
+        let commands: Vec<(u8, String)> = game
+            .events()
+            .into_iter()
+            .filter_map(|e| match e.event {
+                Event::TriggerChat { text } => e.player_id.map(|id| (id, text)),
+                _ => None,
+            })
+            .filter(|(_, text)| text.starts_with('-'))
+            .collect();
+        // received by several triggers, reported once
+        let command = |player: u8, text: &str| (player, text.to_string());
+        assert_eq!(
+            vec![
+                command(6, "-cam 3500"),
+                command(6, "-cheer yee"),
+                command(8, "-observe"),
+                command(3, "-names"),
+            ],
+            commands[..4]
+        );
+    }
+
+    #[test]
+    fn pauses() {
+        let game = Game::from_bytes(&replay_bytes("reforged_metadata_ghostplayer.w3g")).unwrap();
+        let pauses = game.pauses();
+        assert_eq!(3, pauses.len());
+        assert_eq!(7, pauses[0].paused_by);
+        assert_eq!(Some(1), pauses[0].resumed_by);
+        assert_eq!(Duration::from_millis(568838), pauses[0].at);
+        assert_eq!(Duration::from_millis(595824 - 568838), pauses[0].duration);
+        // game time stops while paused
+        let paused: Duration = pauses.iter().map(|p| p.duration).sum();
+        let last = game.events().pop().unwrap();
+        assert_eq!(last.time - paused, last.game_time);
+        assert_eq!(pauses[0].game_time, pauses[0].at);
+        assert_eq!(pauses[1].at - pauses[0].duration, pauses[1].game_time);
+    }
+
+    #[test]
+    fn parse_from_memory() {
+        let bytes = replay_bytes("reforged_release.w3g");
+        let from_bytes = Game::from_bytes(&bytes).unwrap();
+        let from_reader = Game::from_reader(&bytes[..]).unwrap();
+        let from_file = Game::parse(replay("reforged_release.w3g"));
+        assert_eq!(from_file.name, from_bytes.name);
+        assert_eq!(from_file.players, from_bytes.players);
+        assert_eq!(from_file.events(), from_bytes.events());
+        assert_eq!(from_bytes.events(), from_reader.events());
+    }
+
+    #[test]
+    fn computer_players() {
+        let mut game =
+            Game::from_bytes(&replay_bytes("reforged_truncated_playernames.w3g")).unwrap();
+        assert_eq!(24, game.slots.len());
+        assert_eq!(SlotStatus::Occupied, game.slots[0].status);
+        assert_eq!(SlotStatus::Open, game.slots[2].status);
+        assert!(game.players.iter().all(|p| !p.is_computer()));
+        // turn two open slots into computer players
+        for (slot, ai_strength) in game.slot_records[2..4].iter_mut().zip([0, 2]) {
+            slot.slot_status = 2;
+            slot.computer_flag = 1;
+            slot.ai_strength = ai_strength;
+            slot.handicap_flag = 80;
+            slot.team_id = 1;
+        }
+        game.slot_records[3].color = 3;
+        let game = Game::from_bytes(&game.to_bytes().unwrap()).unwrap();
+        assert_eq!(Some(AiDifficulty::Easy), game.slots[2].computer);
+        assert_eq!(80, game.slots[3].handicap);
+        let computers: Vec<(&str, u8)> = game
+            .players
+            .iter()
+            .filter(|p| p.is_computer())
+            .map(|p| (p.name.as_str(), p.handicap))
+            .collect();
+        assert_eq!(
+            vec![("Computer (Easy)", 80), ("Computer (Insane)", 80)],
+            computers
+        );
+        let (_, team) = &game.players_by_team()[1];
+        assert_eq!(3, team.len());
+    }
+
+    #[test]
+    fn truncate_with_computers() {
+        let mut game = with_computers(1);
+        assert_eq!(4, game.players.len());
+        game.truncate(Duration::from_secs(60));
+        let leaves: Vec<(u8, u8)> = game
+            .leave_blocks()
+            .iter()
+            .map(|l| (l.player_id, l.reason[0]))
+            .collect();
+        assert_eq!(vec![(2, LEAVE_REMOTE_GAME), (1, LEAVE_LOCAL_GAME)], leaves);
+        // without a local leave, the last human player saved the replay
+        let mut game = with_computers(1);
+        game.blocks.retain(|b| !matches!(b, GameBlock::Leave(_)));
+        game.truncate(Duration::from_secs(60));
+        let leaves: Vec<(u8, u8)> = game
+            .leave_blocks()
+            .iter()
+            .map(|l| (l.player_id, l.reason[0]))
+            .collect();
+        assert_eq!(vec![(1, LEAVE_REMOTE_GAME), (2, LEAVE_LOCAL_GAME)], leaves);
+    }
+
+    #[test]
+    fn outcome_against_computers() {
+        let bytes = replay_bytes("reforged_truncated_playernames.w3g");
+        let mut game = Game::from_bytes(&bytes).unwrap();
+        // player 2 is replaced by a computer, player 1 leaves without result
+        game.slot_records[1].player_id = 0;
+        game.slot_records[1].computer_flag = 1;
+        let mut game = Game::from_bytes(&game.to_bytes().unwrap()).unwrap();
+        assert!(game.players[1].is_computer());
+        assert_eq!(GameOutcome::Unknown, game.outcome());
+        // player 1 loses against the computer
+        for block in game.blocks.iter_mut() {
+            if let GameBlock::Leave(leave) = block {
+                leave.result = [0x08, 0, 0, 0];
+            }
+        }
+        assert_eq!(GameOutcome::Winner(1), game.outcome());
+        // the computers' team didn't lose against player 1 either
+        let game = with_computers(1);
+        assert_eq!(GameOutcome::Winner(0), game.outcome());
+    }
 }
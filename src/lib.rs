@@ -2,20 +2,28 @@ extern crate colored;
 extern crate nom;
 
 pub mod action;
+pub mod alliance;
+pub mod anonymize;
 mod blocks;
 pub mod building;
+pub mod desync;
 pub mod display;
 pub mod environment;
+pub mod error;
 pub mod event;
 pub mod game;
 pub mod item;
 pub mod map;
 pub mod metadata;
 pub mod player;
+mod protobuf;
 pub mod race;
 pub mod spell;
+pub mod stream;
 pub mod unit;
 mod utils;
+pub mod verify;
+pub mod w3mmd;
 
 #[cfg(test)]
 pub mod tests {
@@ -61,6 +69,19 @@ pub mod tests {
         fs::read(replay(name)).unwrap_or_else(|_| panic!("Could not read {}", name))
     }
 
+    /// A 1v1 whose open slots 2 and 3 are turned into computer players of `team_id`:
+    /// none of the fixtures has computers
+    pub(crate) fn with_computers(team_id: u8) -> Game {
+        let bytes = replay_bytes("reforged_truncated_playernames.w3g");
+        let mut game = Game::from_bytes(&bytes).unwrap();
+        for slot in game.slot_records[2..4].iter_mut() {
+            slot.slot_status = 2;
+            slot.computer_flag = 1;
+            slot.team_id = team_id;
+        }
+        Game::from_bytes(&game.to_bytes().unwrap()).unwrap()
+    }
+
     pub(crate) fn w3info_replay(name: &str) -> PathBuf {
         let mut dir = replays_w3info_dir();
         dir.push(name);
@@ -107,6 +128,22 @@ pub mod tests {
         assert_eq!(1, players.iter().filter(|p| p.is_observer()).count());
     }
 
+    #[test]
+    fn parse_reforged_battle_tags() {
+        let game = Game::parse(replay("reforged_truncated_playernames.w3g"));
+        let names: Vec<&str> = game.players.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(vec!["WaN#1734", "РозовыйПони#228941"], names);
+        assert!(game
+            .players
+            .iter()
+            .all(|p| p.portrait.as_deref() == Some("p051")));
+
+        let game = Game::parse(w3info_replay("3210760876_FeaR_Kiosuke_Northern Isles.w3g"));
+        let observer = game.players.iter().find(|p| p.is_observer()).unwrap();
+        assert_eq!("Kover00#2421", observer.name);
+        assert_eq!(None, observer.clan);
+    }
+
     #[test]
     fn parse_all() {
         let start = SystemTime::now();
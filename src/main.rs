@@ -0,0 +1,95 @@
+use std::env;
+use std::fs;
+use std::process;
+use std::time::Duration;
+use w3rs::anonymize::PrivateChat;
+use w3rs::desync::Comparison;
+use w3rs::error::W3rsError;
+use w3rs::game::Game;
+
+const USAGE: &str = "Usage:
+    w3rs <replay.w3g>                                     display the game
+    w3rs anonymize [--mask-chat] <replay.w3g> <out.w3g>   write an anonymized copy of the replay
+    w3rs verify <replay.w3g>                              check the replay checksums, exits with 1 if corrupted
+    w3rs cut <replay.w3g> <game time> <out.w3g>           write the beginning of the replay, up to `2m30s` for instance
+    w3rs desync <replay.w3g> <other.w3g>                  compare two recordings of a game, exits with 1 if they disagree";
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let res = match args.as_slice() {
+        ["anonymize", "--mask-chat", replay, out] => anonymize(replay, out, PrivateChat::Mask),
+        ["anonymize", replay, out] => anonymize(replay, out, PrivateChat::Drop),
+        ["verify", replay] => verify(replay),
+        ["desync", replay, other] => desync(replay, other),
+        ["cut", replay, at, out] => match humantime::parse_duration(at) {
+            Ok(at) => cut(replay, at, out),
+            Err(e) => {
+                eprintln!("Invalid game time {}: {}", at, e);
+                process::exit(2);
+            }
+        },
+        [replay] if !["anonymize", "verify", "cut", "desync"].contains(replay) => {
+            Game::try_parse(replay).map(|game| print!("{}", game))
+        }
+        _ => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    };
+    if let Err(e) = res {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn anonymize(replay: &str, out: &str, private_chat: PrivateChat) -> Result<(), W3rsError> {
+    let mut game = Game::try_parse(replay)?;
+    game.anonymize(private_chat);
+    fs::write(out, game.to_bytes()?)?;
+    Ok(())
+}
+
+fn cut(replay: &str, at: Duration, out: &str) -> Result<(), W3rsError> {
+    let mut game = Game::try_parse(replay)?;
+    game.truncate(at);
+    fs::write(out, game.to_bytes()?)?;
+    Ok(())
+}
+
+fn verify(replay: &str) -> Result<(), W3rsError> {
+    let report = w3rs::verify::verify(&fs::read(replay)?)?;
+    if !report.header_valid {
+        println!("Header checksum mismatch");
+    }
+    for block in &report.corrupted_blocks {
+        println!("Data block {}/{} is corrupted", block, report.block_count);
+    }
+    if !report.is_valid() {
+        process::exit(1);
+    }
+    println!("OK");
+    Ok(())
+}
+
+fn desync(replay: &str, other: &str) -> Result<(), W3rsError> {
+    let game = Game::try_parse(replay)?;
+    match game.compare(&Game::try_parse(other)?) {
+        Comparison::DifferentGames => {
+            println!("Not recordings of the same game");
+            process::exit(1);
+        }
+        Comparison::Desync(desync) => {
+            println!(
+                "Desync at tick {} ({}): {:08x} vs {:08x}",
+                desync.tick,
+                humantime::format_duration(desync.time),
+                desync.checksums.0,
+                desync.checksums.1
+            );
+            process::exit(1);
+        }
+        Comparison::InSync { ticks } => println!("OK, {} ticks in sync", ticks),
+    }
+    Ok(())
+}
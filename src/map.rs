@@ -1,15 +1,170 @@
 use crate::utils::{zero_terminated, zero_terminated_string};
 use nom::bytes::complete::take;
+use nom::number::complete::{le_u16, le_u32, le_u8};
 use nom::IResult;
 
 #[derive(Debug)]
 pub struct MapInfo {
     pub name: String, // zeroTerminated
+    /// Battle tag of the player who created the game, "Battle.net" for ladder games
+    pub creator: String,
+    pub width: u16,
+    pub height: u16,
+    pub checksum: u32,
 }
 
-pub(crate) fn parse_map_info(input: &[u8]) -> IResult<&[u8], MapInfo> {
-    let (rest, _) = take(13usize)(input)?;
+/// Lobby settings, see: https://gist.github.com/dengzhp/1185519#file-w3g_format-txt-L460
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GameSettings {
+    pub speed: GameSpeed,
+    pub hide_terrain: bool,
+    pub map_explored: bool,
+    pub always_visible: bool,
+    /// Visibility left to the map default
+    pub default_visibility: bool,
+    pub observer_mode: ObserverMode,
+    pub teams_together: bool,
+    pub fixed_teams: bool,
+    pub full_shared_unit_control: bool,
+    pub random_hero: bool,
+    pub random_races: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GameSpeed {
+    Slow,
+    Normal,
+    Fast,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ObserverMode {
+    None,
+    OnDefeat,
+    Full,
+    Referees,
+}
+
+impl From<u8> for GameSpeed {
+    fn from(speed: u8) -> Self {
+        match speed {
+            0 => GameSpeed::Slow,
+            1 => GameSpeed::Normal,
+            2 => GameSpeed::Fast,
+            speed => GameSpeed::Unknown(speed),
+        }
+    }
+}
+
+impl GameSettings {
+    fn from_flags(flags: [u8; 4]) -> GameSettings {
+        let observers = (flags[1] >> 4) & 0b11;
+        let referees = flags[3] & 0b0100_0000 != 0;
+        GameSettings {
+            speed: GameSpeed::from(flags[0] & 0b11),
+            hide_terrain: flags[1] & 0b1 != 0,
+            map_explored: flags[1] & 0b10 != 0,
+            always_visible: flags[1] & 0b100 != 0,
+            default_visibility: flags[1] & 0b1000 != 0,
+            observer_mode: match observers {
+                0 | 3 if referees => ObserverMode::Referees,
+                2 => ObserverMode::OnDefeat,
+                3 => ObserverMode::Full,
+                _ => ObserverMode::None, // 1 in ladder games
+            },
+            teams_together: flags[1] & 0b0100_0000 != 0,
+            fixed_teams: flags[2] & 0b110 != 0,
+            full_shared_unit_control: flags[3] & 0b1 != 0,
+            random_hero: flags[3] & 0b10 != 0,
+            random_races: flags[3] & 0b100 != 0,
+        }
+    }
+}
+
+pub(crate) fn parse_map_info(input: &[u8]) -> IResult<&[u8], (GameSettings, MapInfo)> {
+    let (rest, flags) = take(4usize)(input)?;
+    let (rest, _) = le_u8(rest)?;
+    let (rest, width) = le_u16(rest)?;
+    let (rest, height) = le_u16(rest)?;
+    let (rest, checksum) = le_u32(rest)?;
     let (rest, name) = zero_terminated_string(rest)?;
-    let (rest, _) = zero_terminated(rest)?; // creator name?
-    Ok((rest, MapInfo { name }))
+    let (rest, creator) = zero_terminated_string(rest)?;
+    let settings = GameSettings::from_flags([flags[0], flags[1], flags[2], flags[3]]);
+    Ok((
+        rest,
+        (
+            settings,
+            MapInfo {
+                name,
+                creator,
+                width,
+                height,
+                checksum,
+            },
+        ),
+    ))
+}
+
+/// Splits the map info around the creator name: (settings and map name, creator)
+pub(crate) fn split_map_creator(input: &[u8]) -> IResult<&[u8], (&[u8], &[u8])> {
+    let (rest, _) = take(13usize)(input)?;
+    let (rest, _) = zero_terminated(rest)?;
+    let head = &input[..input.len() - rest.len()];
+    let (rest, creator) = zero_terminated(rest)?;
+    Ok((rest, (head, creator)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::map::{GameSettings, GameSpeed, ObserverMode};
+    use crate::tests::{replay, w3info_replay};
+
+    #[test]
+    fn parse_game_settings() {
+        let game = Game::parse(w3info_replay("3210760876_FeaR_Kiosuke_Northern Isles.w3g"));
+        assert_eq!("Maps/W3Champions\\w3c_NorthernIsles.w3x", game.map.name);
+        assert_eq!("FLO", game.map.creator);
+        assert_eq!(0x2aa69701, game.map.checksum);
+        assert_eq!(
+            GameSettings {
+                speed: GameSpeed::Fast,
+                hide_terrain: false,
+                map_explored: false,
+                always_visible: false,
+                default_visibility: true,
+                observer_mode: ObserverMode::Full,
+                teams_together: true,
+                fixed_teams: true,
+                full_shared_unit_control: false,
+                random_hero: false,
+                random_races: false,
+            },
+            game.settings
+        );
+
+        let ladder = Game::parse(replay("reforged1.w3g"));
+        assert_eq!("Battle.net", ladder.map.creator);
+        assert_eq!(ObserverMode::None, ladder.settings.observer_mode);
+
+        let custom = Game::parse(replay("reforged_hunter2_privatestring.w3g"));
+        assert_eq!("pischner#2950", custom.map.creator);
+        assert_eq!((84, 84), (custom.map.width, custom.map.height));
+    }
+
+    #[test]
+    fn decode_settings_flags() {
+        let settings = GameSettings::from_flags([0x00, 0b0010_0101, 0x00, 0b0100_0110]);
+        assert_eq!(GameSpeed::Slow, settings.speed);
+        assert!(settings.hide_terrain && settings.always_visible);
+        assert!(!settings.map_explored && !settings.teams_together && !settings.fixed_teams);
+        assert_eq!(ObserverMode::OnDefeat, settings.observer_mode);
+        assert!(settings.random_hero && settings.random_races);
+        assert!(!settings.full_shared_unit_control);
+        let referees = GameSettings::from_flags([0x01, 0b0011_0000, 0b110, 0b0100_0000]);
+        assert_eq!(ObserverMode::Referees, referees.observer_mode);
+        assert_eq!(GameSpeed::Normal, referees.speed);
+        assert!(referees.fixed_teams);
+    }
 }
@@ -1,63 +1,174 @@
-use crate::metadata::player::{parse_player_metadata, PlayerMetaData};
-use crate::utils::{zero_terminated, zero_terminated_string};
-use hex_string::u8_to_hex_string;
-use nom::bytes::complete::{take, take_while};
+use crate::game::GameContext;
+use crate::metadata::player::{parse_player_metadata, write_player_metadata, PlayerMetaData};
+use crate::utils::{write_zero_terminated, zero_terminated, zero_terminated_string};
+use nom::bytes::complete::{tag, take, take_while};
 use nom::{
     number::complete::{le_u16, le_u32, le_u8},
     IResult,
 };
-use std::iter::FromIterator;
+use std::convert::TryInto;
 
 #[derive(Debug, PartialEq)]
 pub(crate) struct GameMetaData {
+    unknown: [u8; 4],
     pub host: PlayerMetaData,
     pub game_name: String,
+    pub(crate) private_string: String,
     pub(crate) encoded_map_info: Vec<u8>,
     pub nb_players: u32,
-    game_type: Vec<u8>,
-    language: Vec<u8>,
+    pub(crate) game_type: u32,
+    pub(crate) language: u32,
 }
 
-impl GameMetaData {
-    pub fn game_type(&self) -> Vec<String> {
-        self.game_type
-            .iter()
-            .map(|hex| String::from_iter(u8_to_hex_string(hex).to_vec()))
-            .collect()
+const SINGLE_PLAYER: u32 = 0x04;
+const PRIVATE_GAME: u32 = 1 << 11;
+const MAKER_USER: u32 = 1 << 13;
+const MAKER_BLIZZARD: u32 = 1 << 14;
+const TYPE_MELEE: u32 = 1 << 15;
+const TYPE_SCENARIO: u32 = 1 << 16;
+const SIZE_SMALL: u32 = 1 << 17;
+const SIZE_MEDIUM: u32 = 1 << 18;
+const SIZE_LARGE: u32 = 1 << 19;
+
+/// The game type dword, as advertised to Battle.net when the game was created
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameFlags {
+    pub context: GameContext,
+    pub private: bool,
+    pub map: MapType,
+    pub raw: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MapType {
+    /// Made by Blizzard, every ladder map is
+    pub official: bool,
+    pub melee: bool,
+    pub scenario: bool,
+    pub size: Option<MapSize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MapSize {
+    Small,
+    Medium,
+    Large,
+}
+
+impl From<u32> for GameFlags {
+    fn from(raw: u32) -> Self {
+        let kind = (raw & 0xFF) as u8;
+        let context = if raw & SINGLE_PLAYER != 0 {
+            GameContext::SinglePlayer
+        } else if raw & TYPE_SCENARIO != 0 {
+            GameContext::Scenario
+        } else if kind == 0x09 || raw & MAKER_USER != 0 {
+            GameContext::Custom
+        } else if matches!(kind, 0x01 | 0x10 | 0x20 | 0x40) {
+            GameContext::Ladder
+        } else {
+            GameContext::Unknown(kind)
+        };
+        let size = if raw & SIZE_SMALL != 0 {
+            Some(MapSize::Small)
+        } else if raw & SIZE_MEDIUM != 0 {
+            Some(MapSize::Medium)
+        } else if raw & SIZE_LARGE != 0 {
+            Some(MapSize::Large)
+        } else {
+            None
+        };
+        GameFlags {
+            context,
+            private: raw & PRIVATE_GAME != 0,
+            map: MapType {
+                official: raw & MAKER_BLIZZARD != 0,
+                melee: raw & TYPE_MELEE != 0,
+                scenario: raw & TYPE_SCENARIO != 0,
+                size,
+            },
+            raw,
+        }
+    }
+}
+
+/// Windows language identifier (LCID) of the host's game client
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Unspecified,
+    Locale(&'static str),
+    Unknown(u32),
+}
+
+impl From<u32> for Language {
+    fn from(raw: u32) -> Self {
+        match raw {
+            0 => Language::Unspecified,
+            0x0404 => Language::Locale("zhTW"),
+            0x0407 => Language::Locale("deDE"),
+            0x0409 => Language::Locale("enUS"),
+            0x040A | 0x0C0A => Language::Locale("esES"),
+            0x040C => Language::Locale("frFR"),
+            0x0410 => Language::Locale("itIT"),
+            0x0412 => Language::Locale("koKR"),
+            0x0415 => Language::Locale("plPL"),
+            0x0416 => Language::Locale("ptBR"),
+            0x0419 => Language::Locale("ruRU"),
+            0x0804 => Language::Locale("zhCN"),
+            0x0809 => Language::Locale("enGB"),
+            0x080A => Language::Locale("esMX"),
+            _ => Language::Unknown(raw),
+        }
     }
 }
 
 pub(crate) fn parse_game_metadata(input: &[u8]) -> IResult<&[u8], GameMetaData> {
-    let (rest, _) = take(5usize)(input)?;
+    let (rest, unknown) = take(4usize)(input)?;
+    let (rest, _) = tag([0x00])(rest)?; // host record
     let (rest, host) = parse_player_metadata(rest)?;
     let (rest, game_name) = zero_terminated_string(rest)?;
-    let (rest, _) = zero_terminated_string(rest)?; // private string
+    let (rest, private_string) = zero_terminated_string(rest)?;
     let (rest, encoded_map_info) = zero_terminated(rest)?;
     let (rest, nb_players) = le_u32(rest)?;
-    let (rest, game_type) = take(4usize)(rest)?;
-    let (rest, language) = take(4usize)(rest)?;
+    let (rest, game_type) = le_u32(rest)?;
+    let (rest, language) = le_u32(rest)?;
     Ok((
         rest,
         GameMetaData {
+            unknown: unknown.try_into().unwrap(),
             host,
             game_name,
+            private_string,
             encoded_map_info: encoded_map_info.to_vec(),
             nb_players,
-            game_type: game_type.to_vec(),
-            language: language.to_vec(),
+            game_type,
+            language,
         },
     ))
 }
 
+pub(crate) fn write_game_metadata(out: &mut Vec<u8>, metadata: &GameMetaData) {
+    out.extend_from_slice(&metadata.unknown);
+    out.push(0x00);
+    write_player_metadata(out, &metadata.host);
+    write_zero_terminated(out, metadata.game_name.as_bytes());
+    write_zero_terminated(out, metadata.private_string.as_bytes());
+    write_zero_terminated(out, &metadata.encoded_map_info);
+    out.extend_from_slice(&metadata.nb_players.to_le_bytes());
+    out.extend_from_slice(&metadata.game_type.to_le_bytes());
+    out.extend_from_slice(&metadata.language.to_le_bytes());
+}
+
 #[derive(Debug, PartialEq)]
 pub struct GameStartRecord {
-    data_byte_count: u16,
+    pub(crate) data_byte_count: u16,
     pub(crate) slot_record_count: u8,
 }
 
 pub fn parse_start_record(input: &[u8]) -> IResult<&[u8], GameStartRecord> {
     let (rest, _) = take_while(|b: u8| b != 0x19)(input)?;
-    let (rest, data_byte_count) = le_u16(&rest[1..])?;
+    let (rest, _) = tag([0x19])(rest)?;
+    let (rest, data_byte_count) = le_u16(rest)?;
     let (rest, slot_record_count) = le_u8(rest)?;
     Ok((
         rest,
@@ -68,11 +179,55 @@ pub fn parse_start_record(input: &[u8]) -> IResult<&[u8], GameStartRecord> {
     ))
 }
 
+pub(crate) fn write_start_record(out: &mut Vec<u8>, record: &GameStartRecord) {
+    out.push(0x19);
+    out.extend_from_slice(&record.data_byte_count.to_le_bytes());
+    out.push(record.slot_record_count);
+}
+
 #[derive(Debug, PartialEq)]
 pub struct GamePosData {
-    random_seed: u32,
-    select_mode: [char; 2], // hex, 1 byte
-    start_spot_count: u8,
+    /// Shared by every player of the game: replays saved by different players have the same one
+    pub random_seed: u32,
+    pub select_mode: SelectMode,
+    pub start_spot_count: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelectMode {
+    TeamAndRaceSelectable,
+    FixedTeams,
+    FixedTeamsAndRaces,
+    RandomRaces,
+    /// Ladder games
+    AutomatedMatchmaking,
+    Unknown(u8),
+}
+
+impl From<u8> for SelectMode {
+    fn from(mode: u8) -> Self {
+        match mode {
+            0x00 => SelectMode::TeamAndRaceSelectable,
+            0x01 => SelectMode::FixedTeams,
+            0x03 => SelectMode::FixedTeamsAndRaces,
+            0x04 => SelectMode::RandomRaces,
+            0xCC => SelectMode::AutomatedMatchmaking,
+            mode => SelectMode::Unknown(mode),
+        }
+    }
+}
+
+impl From<SelectMode> for u8 {
+    fn from(mode: SelectMode) -> Self {
+        match mode {
+            SelectMode::TeamAndRaceSelectable => 0x00,
+            SelectMode::FixedTeams => 0x01,
+            SelectMode::FixedTeamsAndRaces => 0x03,
+            SelectMode::RandomRaces => 0x04,
+            SelectMode::AutomatedMatchmaking => 0xCC,
+            SelectMode::Unknown(mode) => mode,
+        }
+    }
 }
 
 pub fn parse_game_pos(input: &[u8]) -> IResult<&[u8], GamePosData> {
@@ -83,18 +238,25 @@ pub fn parse_game_pos(input: &[u8]) -> IResult<&[u8], GamePosData> {
         rest,
         GamePosData {
             random_seed,
-            select_mode: u8_to_hex_string(&select_mode),
+            select_mode: SelectMode::from(select_mode),
             start_spot_count,
         },
     ))
 }
 
+pub(crate) fn write_game_pos(out: &mut Vec<u8>, pos: &GamePosData) {
+    out.extend_from_slice(&pos.random_seed.to_le_bytes());
+    out.push(pos.select_mode.into());
+    out.push(pos.start_spot_count);
+}
+
 #[cfg(test)]
 mod tests {
     use crate::blocks::compressedblock::{compressed_data_blocks, deflate_game};
-    use crate::metadata::game::parse_game_metadata;
-    use crate::metadata::replay::parse_header;
-    use crate::tests::{replays_dir, replays_ignore_dir, replays_w3info_dir};
+    use crate::game::{Game, GameContext};
+    use crate::metadata::game::{parse_game_metadata, GameFlags, Language, MapSize, SelectMode};
+    use crate::metadata::replay::{parse_header, ReplayHeader};
+    use crate::tests::{replay, replays_dir, replays_ignore_dir, replays_w3info_dir};
     use std::ffi::OsStr;
     use std::fs;
     use std::fs::DirEntry;
@@ -102,8 +264,9 @@ mod tests {
 
     fn metadata_parsed_properly(file: DirEntry) {
         let file = fs::read(file.path()).expect("Can read replay as bytes");
-        let (rest, _) = parse_header(&file[..]).unwrap();
-        let (_, blocks) = compressed_data_blocks(rest).unwrap();
+        let (rest, header) = parse_header(&file[..]).unwrap();
+        let version = ReplayHeader::from(&header).game_version();
+        let (_, blocks) = compressed_data_blocks(version)(rest).unwrap();
         let decoded = deflate_game(&blocks).unwrap();
         let res = parse_game_metadata(&decoded);
         assert!(res.is_ok());
@@ -125,4 +288,54 @@ mod tests {
         replays_metadata_is_parsed(replays_ignore_dir());
         replays_metadata_is_parsed(replays_w3info_dir())
     }
+
+    #[test]
+    fn game_flags() {
+        let ladder = Game::parse(replay("reforged1.w3g"));
+        assert_eq!(GameContext::Ladder, ladder.flags.context);
+        assert!(ladder.flags.map.official);
+        assert!(!ladder.flags.private);
+        assert_eq!(Language::Unspecified, ladder.language);
+
+        let private = Game::parse(replay("reforged_hunter2_privatestring.w3g"));
+        assert_eq!(GameContext::Custom, private.flags.context);
+        assert!(private.flags.private);
+        assert!(private.flags.map.melee);
+        assert_eq!(Some(MapSize::Small), private.flags.map.size);
+
+        let scenario = Game::parse(replay("reforged_metadata_ghostplayer.w3g"));
+        assert_eq!(GameContext::Scenario, scenario.flags.context);
+        assert!(!scenario.flags.map.melee);
+        assert_eq!(Some(MapSize::Large), scenario.flags.map.size);
+
+        assert_eq!(GameContext::SinglePlayer, GameFlags::from(0x1d).context);
+        assert_eq!(
+            GameContext::Unknown(0x00),
+            GameFlags::from(0x0010_0000).context
+        );
+        assert_eq!(Language::Locale("frFR"), Language::from(0x040C));
+        assert_eq!(Language::Unknown(0x1234), Language::from(0x1234));
+    }
+
+    #[test]
+    fn game_pos() {
+        // w3info replays are named after their random seed
+        for file in fs::read_dir(replays_w3info_dir())
+            .unwrap()
+            .map(|f| f.unwrap().path())
+            .filter(|f| f.extension() == Some(OsStr::new("w3g")))
+        {
+            let game = Game::parse(&file);
+            let name = file.file_name().unwrap().to_string_lossy().to_string();
+            let seed = name.split('_').next().unwrap();
+            assert_eq!(seed, game.pos.random_seed.to_string());
+            assert_eq!(SelectMode::TeamAndRaceSelectable, game.pos.select_mode);
+            assert_eq!(2, game.pos.start_spot_count);
+        }
+        let game = Game::parse(replay("reforged_metadata_ghostplayer.w3g"));
+        assert_eq!(SelectMode::FixedTeamsAndRaces, game.pos.select_mode);
+        assert_eq!(12, game.pos.start_spot_count);
+        assert_eq!(SelectMode::AutomatedMatchmaking, SelectMode::from(0xCC));
+        assert_eq!(0x42u8, SelectMode::from(0x42).into());
+    }
 }
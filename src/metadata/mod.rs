@@ -1,3 +1,5 @@
 pub(crate) mod game;
 pub mod player;
-pub(crate) mod replay;
+pub mod replay;
+
+pub use game::{GameFlags, GamePosData, Language, MapSize, MapType, SelectMode};
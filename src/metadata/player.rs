@@ -1,20 +1,50 @@
+use crate::metadata::replay::GameVersion;
+use crate::protobuf::{message, write_field, WireValue};
 use crate::race::Race;
-use crate::utils::zero_terminated_string;
+use crate::utils::{write_zero_terminated, zero_terminated};
 use nom::bytes::complete::take;
+use nom::combinator::cond;
 use nom::number::complete::le_u32;
 use nom::{number::complete::le_u8, IResult};
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) struct PlayerMetaData {
     pub id: u8,
-    pub name: String,
+    /// Raw bytes: names can be truncated in the middle of a UTF-8 character
+    pub name: Vec<u8>,
+    pub(crate) additional_data: Vec<u8>,
 }
 
+impl PlayerMetaData {
+    pub(crate) fn name(&self) -> String {
+        String::from_utf8_lossy(&self.name).to_string()
+    }
+}
+
+/// A raw `0x39` record, only players (subtype 3) are decoded
 #[derive(Debug, PartialEq, Clone)]
-// TODO: that's a protobuf payload to be parsed
+pub(crate) struct ReforgedRecord {
+    pub(crate) subtype: u8,
+    pub(crate) payload: Vec<u8>,
+}
+
+impl ReforgedRecord {
+    /// Rewrites battle tags with `names` (by player id) and clears clans.
+    /// Other fields are written back as-is
+    pub(crate) fn rename_players(&mut self, names: &dyn Fn(u8) -> String) {
+        if self.subtype != REFORGED_PLAYER_SUBTYPE {
+            return;
+        }
+        if let Ok((_, payload)) = rename_reforged_players(&self.payload, names) {
+            self.payload = payload;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
 pub(crate) struct ReforgedPlayerMetaData {
     pub id: u32,
-    pub batte_tag: String,
+    pub battle_tag: String,
     pub clan: String,
     pub portrait: String,
     pub team: u32,
@@ -24,109 +54,265 @@ pub(crate) struct ReforgedPlayerMetaData {
 #[derive(Debug, PartialEq)]
 pub(crate) struct PlayerSlotMetaData {
     pub(crate) player_id: u8,
-    slot_status: u8,
-    computer_flag: u8,
+    pub(crate) download_percent: u8,
+    pub(crate) slot_status: u8,
+    pub(crate) computer_flag: u8,
     pub(crate) team_id: u8,
     pub(crate) color: u8,
     pub(crate) race: Race,
-    ai_strength: u8,
-    handicap_flag: u8,
+    pub(crate) race_flag: u8,
+    pub(crate) ai_strength: u8,
+    pub(crate) handicap_flag: u8,
 }
 
 pub(crate) fn parse_player_metadata(input: &[u8]) -> IResult<&[u8], PlayerMetaData> {
     let (rest, id) = le_u8(input)?;
-    let (rest, name) = zero_terminated_string(rest)?;
+    let (rest, name) = zero_terminated(rest)?;
     let (rest, add_data_flag) = le_u8(rest)?;
-    let (rest, _) = take(add_data_flag as usize)(rest)?;
-    Ok((rest, PlayerMetaData { id, name }))
+    let (rest, additional_data) = take(add_data_flag as usize)(rest)?;
+    Ok((
+        rest,
+        PlayerMetaData {
+            id,
+            name: name.to_vec(),
+            additional_data: additional_data.to_vec(),
+        },
+    ))
+}
+
+pub(crate) fn write_player_metadata(out: &mut Vec<u8>, player: &PlayerMetaData) {
+    out.push(player.id);
+    write_zero_terminated(out, &player.name);
+    out.push(player.additional_data.len() as u8);
+    out.extend_from_slice(&player.additional_data);
 }
 
+/// Decodes a single protobuf `PlayerMetadata` message
 pub(crate) fn parse_reforged_player_metadata(
-    _input: &[u8],
+    input: &[u8],
 ) -> IResult<&[u8], ReforgedPlayerMetaData> {
-    // TODO: protobuf parsing
-    unimplemented!("Protobuf parsing of Reforged players hasn't been implemented yet");
+    let (rest, fields) = message(input)?;
+    let mut player = ReforgedPlayerMetaData::default();
+    for (number, value) in fields {
+        match number {
+            1 => player.id = value.as_u32().unwrap_or_default(),
+            2 => player.battle_tag = value.as_string().unwrap_or_default(),
+            3 => player.clan = value.as_string().unwrap_or_default(),
+            4 => player.portrait = value.as_string().unwrap_or_default(),
+            5 => player.team = value.as_u32().unwrap_or_default(),
+            6 => player.unknown = value.as_string().unwrap_or_default(),
+            _ => {}
+        }
+    }
+    Ok((rest, player))
+}
+
+/// Older Reforged builds wrap every player in a single record (repeated field 1),
+/// newer ones write one record per player
+fn parse_reforged_players_record(input: &[u8]) -> IResult<&[u8], Vec<ReforgedPlayerMetaData>> {
+    let (rest, fields) = message(input)?;
+    let wrapped: Vec<&[u8]> = fields
+        .iter()
+        .filter(|(number, _)| *number == 1)
+        .filter_map(|(_, value)| value.as_bytes())
+        .collect();
+    if wrapped.is_empty() {
+        let (_, player) = parse_reforged_player_metadata(input)?;
+        return Ok((rest, vec![player]));
+    }
+    let mut players = Vec::new();
+    for payload in wrapped {
+        let (_, player) = parse_reforged_player_metadata(payload)?;
+        players.push(player);
+    }
+    Ok((rest, players))
+}
+
+fn rename_reforged_player<'a>(
+    input: &'a [u8],
+    names: &dyn Fn(u8) -> String,
+) -> IResult<&'a [u8], Vec<u8>> {
+    let (rest, fields) = message(input)?;
+    let name = fields
+        .iter()
+        .find(|(number, _)| *number == 1)
+        .and_then(|(_, id)| id.as_u32())
+        .map(|id| names(id as u8));
+    let mut out = Vec::with_capacity(input.len());
+    for (number, value) in &fields {
+        match (number, &name) {
+            (2, Some(name)) => write_field(&mut out, 2, &WireValue::Bytes(name.as_bytes())),
+            (3, Some(_)) => write_field(&mut out, 3, &WireValue::Bytes(&[])),
+            _ => write_field(&mut out, *number, value),
+        }
+    }
+    Ok((rest, out))
+}
+
+/// Same layouts as `parse_reforged_players_record`
+fn rename_reforged_players<'a>(
+    input: &'a [u8],
+    names: &dyn Fn(u8) -> String,
+) -> IResult<&'a [u8], Vec<u8>> {
+    let (rest, fields) = message(input)?;
+    if !fields
+        .iter()
+        .any(|(number, value)| *number == 1 && value.as_bytes().is_some())
+    {
+        return rename_reforged_player(input, names);
+    }
+    let mut out = Vec::with_capacity(input.len());
+    for (number, value) in &fields {
+        match value.as_bytes() {
+            Some(payload) if *number == 1 => {
+                let (_, player) = rename_reforged_player(payload, names)?;
+                write_field(&mut out, 1, &WireValue::Bytes(&player));
+            }
+            _ => write_field(&mut out, *number, value),
+        }
+    }
+    Ok((rest, out))
 }
 
 pub(crate) fn parse_players(input: &[u8]) -> IResult<&[u8], Vec<PlayerMetaData>> {
     let mut rest = input;
     let mut players = Vec::new();
-    let mut fst = rest[0];
-    while fst == 0x16 {
+    while rest.first() == Some(&0x16) {
         // https://gist.github.com/ForNeVeR/48dfcf05626abb70b35b8646dd0d6e92#file-w3g_format-txt-L627
         let (bytes, _) = take(1usize)(rest)?;
         let (bytes, player) = parse_player_metadata(bytes)?;
         let (bytes, _) = take(4usize)(bytes)?;
         rest = bytes;
         players.push(player);
-        fst = bytes[0];
     }
     Ok((rest, players))
 }
 
-// const REFORGED_PLAYER_SUBTYPE: u8 = 0x3;
-const REFORGED_PLAYER_SUBTYPE: u8 = 0x0; // FIXME: use the one above
+pub(crate) fn write_players(out: &mut Vec<u8>, players: &[PlayerMetaData]) {
+    for player in players {
+        out.push(0x16);
+        write_player_metadata(out, player);
+        out.extend_from_slice(&[0; 4]);
+    }
+}
+
+const REFORGED_PLAYER_SUBTYPE: u8 = 0x3;
 
-pub(crate) fn parse_players_reforged(input: &[u8]) -> IResult<&[u8], Vec<ReforgedPlayerMetaData>> {
+pub(crate) fn parse_players_reforged(
+    input: &[u8],
+) -> IResult<&[u8], (Vec<ReforgedRecord>, Vec<ReforgedPlayerMetaData>)> {
     let mut rest = input;
+    let mut records = Vec::new();
     let mut players = Vec::new();
-    while rest[0] == 0x39 {
+    while rest.first() == Some(&0x39) {
         // https://gist.github.com/ForNeVeR/48dfcf05626abb70b35b8646dd0d6e92#file-w3g_format-txt-L627
         let (bytes, _) = take(1usize)(rest)?;
         let (bytes, subtype) = le_u8(bytes)?;
         let (bytes, following_bytes) = le_u32(bytes)?;
         let (bytes, payload) = take(following_bytes)(bytes)?;
         if subtype == REFORGED_PLAYER_SUBTYPE {
-            // TODO: Protobuf parsing here
-            let (_, player) = parse_reforged_player_metadata(payload)?;
-            players.push(player);
+            let (_, record) = parse_reforged_players_record(payload)?;
+            for player in record {
+                // some records are repeated (observers, mostly)
+                if !players
+                    .iter()
+                    .any(|p: &ReforgedPlayerMetaData| p.id == player.id)
+                {
+                    players.push(player);
+                }
+            }
         }
+        records.push(ReforgedRecord {
+            subtype,
+            payload: payload.to_vec(),
+        });
         rest = bytes;
-        // players.push(player);
     }
-    Ok((rest, players))
+    Ok((rest, (records, players)))
+}
+
+pub(crate) fn write_players_reforged(out: &mut Vec<u8>, records: &[ReforgedRecord]) {
+    for record in records {
+        out.push(0x39);
+        out.push(record.subtype);
+        out.extend_from_slice(&(record.payload.len() as u32).to_le_bytes());
+        out.extend_from_slice(&record.payload);
+    }
 }
 
 pub(crate) fn parse_players_slots(
     nb_players: u8,
+    version: GameVersion,
 ) -> impl Fn(&[u8]) -> IResult<&[u8], Vec<PlayerSlotMetaData>> {
     move |input| {
-        std::iter::repeat(parse_player_slot_record)
-            .take(nb_players as usize)
-            .try_fold((input, Vec::new()), |(data, mut acc), parser| {
-                parser(data).map(|(i, o)| {
+        std::iter::repeat_n(parse_player_slot_record, nb_players as usize).try_fold(
+            (input, Vec::new()),
+            |(data, mut acc), parser| {
+                parser(data, version).map(|(i, o)| {
                     acc.push(o);
                     (i, acc)
                 })
-            })
+            },
+        )
     }
 }
 
-fn parse_player_slot_record(input: &[u8]) -> IResult<&[u8], PlayerSlotMetaData> {
+fn parse_player_slot_record(
+    input: &[u8],
+    version: GameVersion,
+) -> IResult<&[u8], PlayerSlotMetaData> {
+    let record_size = version.slot_record_size();
     let (rest, player_id) = le_u8(input)?;
-    let (rest, _) = take(1usize)(rest)?;
+    let (rest, download_percent) = le_u8(rest)?;
     let (rest, slot_status) = le_u8(rest)?;
     let (rest, computer_flag) = le_u8(rest)?;
     let (rest, team_id) = le_u8(rest)?;
     let (rest, color) = le_u8(rest)?;
     let (rest, race_flag) = le_u8(rest)?;
-    let (rest, ai_strength) = le_u8(rest)?;
-    let (rest, handicap_flag) = le_u8(rest)?;
+    let (rest, ai_strength) = cond(record_size >= 8, le_u8)(rest)?;
+    let (rest, handicap_flag) = cond(record_size >= 9, le_u8)(rest)?;
     Ok((
         rest,
         PlayerSlotMetaData {
             player_id,
+            download_percent,
             slot_status,
             computer_flag,
             team_id,
             color,
             race: Race::from_u8(race_flag),
-            ai_strength,
-            handicap_flag,
+            race_flag,
+            ai_strength: ai_strength.unwrap_or(1), // normal
+            handicap_flag: handicap_flag.unwrap_or(100),
         },
     ))
 }
 
+pub(crate) fn write_players_slots(
+    out: &mut Vec<u8>,
+    slots: &[PlayerSlotMetaData],
+    version: GameVersion,
+) {
+    let record_size = version.slot_record_size();
+    for slot in slots {
+        out.extend_from_slice(&[
+            slot.player_id,
+            slot.download_percent,
+            slot.slot_status,
+            slot.computer_flag,
+            slot.team_id,
+            slot.color,
+            slot.race_flag,
+        ]);
+        if record_size >= 8 {
+            out.push(slot.ai_strength);
+        }
+        if record_size >= 9 {
+            out.push(slot.handicap_flag);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     // fn test_replay(file: DirEntry) {
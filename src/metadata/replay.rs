@@ -1,17 +1,19 @@
-use crate::utils::zero_terminated;
+use crate::utils::{crc32, zero_terminated};
 use nom::bytes::complete::{tag, take};
 use nom::{
     number::complete::{le_u16, le_u32},
     IResult,
 };
+use std::time::Duration;
 
 const REPLAY_PREFIX: &str = "Warcraft III recorded game";
+const REPLAY_MAGIC: &[u8] = b"\x1A\0";
 
 #[derive(Debug, PartialEq, Eq)]
 pub(crate) struct FileMetaData {
     pub offset: u32,
     pub compressed_size: u32,
-    pub header_version: String,
+    pub header_version: u32,
     pub decompressed_size: u32,
     pub compressed_data_block_count: u32,
     pub replay_metadata: ReplayMetaData,
@@ -22,26 +24,150 @@ pub(crate) struct ReplayMetaData {
     pub game_identifier: String,
     pub version: u32,
     pub build_no: u16,
-    pub flags: String,
+    pub flags: u16,
     pub replay_length_ms: u32,
     pub checksum: u32,
 }
 
+impl FileMetaData {
+    pub(crate) fn header_size(header_version: u32) -> u32 {
+        if header_version == 0 {
+            0x40
+        } else {
+            0x44
+        }
+    }
+}
+
+const MULTIPLAYER_FLAG: u16 = 0x8000;
+const CLASSIC_GAME_IDENTIFIER: &str = "3RAW";
+const EXPANSION_GAME_IDENTIFIER: &str = "PX3W";
+
+/// What the parsers need to know to handle version-specific layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GameVersion {
+    /// 26 for 1.26, 32 for 1.32, etc.
+    pub patch: u32,
+    pub build_no: u16,
+}
+
+impl GameVersion {
+    /// Reforged data blocks use 32bits sizes: 12 bytes headers instead of 8
+    pub(crate) fn has_wide_block_header(&self) -> bool {
+        self.patch >= 32
+    }
+
+    /// AI strength has been added to slot records in 1.03, handicap in 1.07
+    pub(crate) fn slot_record_size(&self) -> usize {
+        if self.patch < 3 {
+            7
+        } else if self.patch < 7 {
+            8
+        } else {
+            9
+        }
+    }
+
+    /// Ability flags were a single byte before 1.13
+    pub(crate) fn has_short_ability_flags(&self) -> bool {
+        self.patch < 13
+    }
+
+    /// Ability actions gained two unknown dwords after the item id in 1.07
+    pub(crate) fn has_ability_unknowns(&self) -> bool {
+        self.patch >= 7
+    }
+
+    /// 1.14b introduced the "pre-subselection" action (0x1A), shifting the following ids.
+    /// 1.14 and 1.14b can't be told apart, 1.14 is considered as 1.14b
+    pub(crate) fn has_legacy_subgroup_actions(&self) -> bool {
+        self.patch < 14
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplayHeader {
+    /// `true` for The Frozen Throne (W3XP), `false` for Reign of Chaos (WAR3)
+    pub expansion: bool,
+    /// 26 for 1.26, 10032 for 1.32, etc.
+    pub version: u32,
+    pub build_no: u16,
+    pub flags: u16,
+    pub duration: Duration,
+    pub decompressed_size: u32,
+}
+
+impl ReplayHeader {
+    pub(crate) fn game_version(&self) -> GameVersion {
+        GameVersion {
+            patch: self.version % 10000,
+            build_no: self.build_no,
+        }
+    }
+
+    pub fn is_multiplayer(&self) -> bool {
+        self.flags & MULTIPLAYER_FLAG != 0
+    }
+
+    pub fn is_single_player(&self) -> bool {
+        !self.is_multiplayer()
+    }
+
+    /// Human-readable patch version, like "1.32"
+    pub fn patch(&self) -> String {
+        format!("1.{:02}", self.version % 10000)
+    }
+
+    /// 0 up to 1.06, 1 for later Reign of Chaos patches, The Frozen Throne and Reforged
+    pub(crate) fn header_version(&self) -> u32 {
+        if !self.expansion && self.version < 7 {
+            0
+        } else {
+            1
+        }
+    }
+
+    pub(crate) fn game_identifier(&self) -> &'static str {
+        if self.expansion {
+            EXPANSION_GAME_IDENTIFIER
+        } else {
+            CLASSIC_GAME_IDENTIFIER
+        }
+    }
+}
+
+impl From<&FileMetaData> for ReplayHeader {
+    fn from(file: &FileMetaData) -> Self {
+        ReplayHeader {
+            expansion: file.replay_metadata.game_identifier == EXPANSION_GAME_IDENTIFIER,
+            version: file.replay_metadata.version,
+            build_no: file.replay_metadata.build_no,
+            flags: file.replay_metadata.flags,
+            duration: Duration::from_millis(file.replay_metadata.replay_length_ms as u64),
+            decompressed_size: file.decompressed_size,
+        }
+    }
+}
+
 pub(crate) fn parse_header(input: &[u8]) -> IResult<&[u8], FileMetaData> {
     let (rest, _) = tag(REPLAY_PREFIX)(input)?;
     let (rest, _) = zero_terminated(rest)?; // "magic" string
     let (rest, offset) = le_u32(rest)?;
     let (rest, compressed_size) = le_u32(rest)?;
-    let (rest, header_version) = take(4usize)(rest)?;
+    let (rest, header_version) = le_u32(rest)?;
     let (rest, decompressed_size) = le_u32(rest)?;
     let (rest, compressed_data_block_count) = le_u32(rest)?;
-    let (rest, replay_metadata) = parse_replay_metadata(rest)?;
+    let (rest, replay_metadata) = if header_version == 0 {
+        parse_classic_replay_metadata(rest)?
+    } else {
+        parse_replay_metadata(rest)?
+    };
     Ok((
         rest,
         FileMetaData {
             offset,
             compressed_size,
-            header_version: String::from_utf8_lossy(header_version).to_string(),
+            header_version,
             decompressed_size,
             compressed_data_block_count,
             replay_metadata,
@@ -53,28 +179,126 @@ fn parse_replay_metadata(input: &[u8]) -> IResult<&[u8], ReplayMetaData> {
     let (rest, game_identifier) = take(4usize)(input)?;
     let (rest, version) = le_u32(rest)?;
     let (rest, build_no) = le_u16(rest)?;
-    let (rest, flags) = take(2usize)(rest)?;
+    let (rest, flags) = le_u16(rest)?;
     let (rest, replay_length_ms) = le_u32(rest)?;
     let (rest, checksum) = le_u32(rest)?;
-    // let (rest, _checksum_sha1) = le_u32(rest)?;
-    // let metadata = ReplayMetaData {
-    //     game_identifier: String::from_utf8_lossy(game_identifier).to_string(),
-    //     version,
-    //     build_no,
-    //     flags: String::from_utf8_lossy(flags).to_string(),
-    //     replay_length_ms,
-    //     checksum,
-    // };
-    // println!("metadata: {metadata:?}");
     Ok((
         rest,
         ReplayMetaData {
             game_identifier: String::from_utf8_lossy(game_identifier).to_string(),
             version,
             build_no,
-            flags: String::from_utf8_lossy(flags).to_string(),
+            flags,
             replay_length_ms,
             checksum,
         },
     ))
 }
+
+/// Writes the header back, the checksum is recomputed
+pub(crate) fn write_header(out: &mut Vec<u8>, file: &FileMetaData) {
+    let start = out.len();
+    let metadata = &file.replay_metadata;
+    out.extend_from_slice(REPLAY_PREFIX.as_bytes());
+    out.extend_from_slice(REPLAY_MAGIC);
+    out.extend_from_slice(&file.offset.to_le_bytes());
+    out.extend_from_slice(&file.compressed_size.to_le_bytes());
+    out.extend_from_slice(&file.header_version.to_le_bytes());
+    out.extend_from_slice(&file.decompressed_size.to_le_bytes());
+    out.extend_from_slice(&file.compressed_data_block_count.to_le_bytes());
+    if file.header_version == 0 {
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&(metadata.version as u16).to_le_bytes());
+    } else {
+        out.extend_from_slice(metadata.game_identifier.as_bytes());
+        out.extend_from_slice(&metadata.version.to_le_bytes());
+    }
+    out.extend_from_slice(&metadata.build_no.to_le_bytes());
+    out.extend_from_slice(&metadata.flags.to_le_bytes());
+    out.extend_from_slice(&metadata.replay_length_ms.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    let checksum = crc32(&out[start..]);
+    let len = out.len();
+    out[len - 4..].copy_from_slice(&checksum.to_le_bytes());
+}
+
+/// Header version 0: Reign of Chaos replays, up to 1.06
+fn parse_classic_replay_metadata(input: &[u8]) -> IResult<&[u8], ReplayMetaData> {
+    let (rest, _) = le_u16(input)?;
+    let (rest, version) = le_u16(rest)?;
+    let (rest, build_no) = le_u16(rest)?;
+    let (rest, flags) = le_u16(rest)?;
+    let (rest, replay_length_ms) = le_u32(rest)?;
+    let (rest, checksum) = le_u32(rest)?;
+    Ok((
+        rest,
+        ReplayMetaData {
+            game_identifier: CLASSIC_GAME_IDENTIFIER.to_string(),
+            version: version as u32,
+            build_no,
+            flags,
+            replay_length_ms,
+            checksum,
+        },
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::metadata::replay::{parse_header, write_header, ReplayHeader};
+    use crate::tests::replay_bytes;
+    use std::time::Duration;
+
+    #[test]
+    fn parse_replay_header() {
+        let file = replay_bytes("reforged1.w3g");
+        let (_, metadata) = parse_header(&file).unwrap();
+        let header = ReplayHeader::from(&metadata);
+        assert!(header.expansion);
+        assert_eq!(10032, header.version);
+        assert_eq!("1.32", header.patch());
+        assert_eq!(6091, header.build_no);
+        assert!(header.is_multiplayer());
+        assert_eq!(Duration::from_millis(276625), header.duration);
+        assert_eq!(92419, header.decompressed_size);
+        assert!(header.game_version().has_wide_block_header());
+    }
+
+    #[test]
+    fn write_replay_header() {
+        let file = replay_bytes("reforged2010.w3g");
+        let (_, metadata) = parse_header(&file).unwrap();
+        let mut written = Vec::new();
+        write_header(&mut written, &metadata);
+        assert_eq!(&file[..metadata.offset as usize], &written[..]);
+    }
+
+    #[test]
+    fn parse_classic_replay_header() {
+        let mut file = b"Warcraft III recorded game\x1A\0".to_vec();
+        file.extend_from_slice(&0x40u32.to_le_bytes()); // header size
+        file.extend_from_slice(&1000u32.to_le_bytes()); // compressed size
+        file.extend_from_slice(&0u32.to_le_bytes()); // header version
+        file.extend_from_slice(&8192u32.to_le_bytes()); // decompressed size
+        file.extend_from_slice(&1u32.to_le_bytes()); // blocks
+        file.extend_from_slice(&0u16.to_le_bytes());
+        file.extend_from_slice(&6u16.to_le_bytes()); // 1.06
+        file.extend_from_slice(&4656u16.to_le_bytes());
+        file.extend_from_slice(&0x8000u16.to_le_bytes());
+        file.extend_from_slice(&60000u32.to_le_bytes());
+        file.extend_from_slice(&0u32.to_le_bytes()); // checksum
+        assert_eq!(0x40, file.len());
+        let (rest, metadata) = parse_header(&file).unwrap();
+        assert!(rest.is_empty());
+        let header = ReplayHeader::from(&metadata);
+        assert!(!header.expansion);
+        assert_eq!("1.06", header.patch());
+        assert_eq!(4656, header.build_no);
+        assert!(header.is_multiplayer());
+        assert_eq!(Duration::from_secs(60), header.duration);
+        let version = header.game_version();
+        assert!(!version.has_wide_block_header());
+        assert!(version.has_short_ability_flags());
+        assert_eq!(8, version.slot_record_size());
+    }
+}
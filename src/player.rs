@@ -1,3 +1,4 @@
+use crate::metadata::player::{PlayerSlotMetaData, ReforgedPlayerMetaData};
 use crate::race::Race;
 use std::hash::{Hash, Hasher};
 
@@ -9,9 +10,94 @@ pub struct Player {
     pub race: Race,
     pub color: u8,
     pub host: bool,
+    /// Reforged only
+    pub clan: Option<String>,
+    /// Reforged only
+    pub portrait: Option<String>,
+    /// Computer players have no id of their own (0), only their slot tells them apart
+    pub computer: Option<AiDifficulty>,
+    /// Percentage of the usual hit points
+    pub handicap: u8,
+}
+
+/// A lobby slot, whether someone played in it or not
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot {
+    /// 0 for open, closed and computer slots
+    pub player_id: u8,
+    pub status: SlotStatus,
+    pub computer: Option<AiDifficulty>,
+    pub team_id: u8,
+    pub color: u8,
+    pub race: Race,
+    pub handicap: u8,
+    /// 255 if there is no human player
+    pub download_percent: u8,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SlotStatus {
+    Open,
+    Closed,
+    Occupied,
+    Unknown(u8),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AiDifficulty {
+    Easy,
+    Normal,
+    Insane,
+    Unknown(u8),
+}
+
+impl From<&PlayerSlotMetaData> for Slot {
+    fn from(slot: &PlayerSlotMetaData) -> Self {
+        Slot {
+            player_id: slot.player_id,
+            status: match slot.slot_status {
+                0 => SlotStatus::Open,
+                1 => SlotStatus::Closed,
+                2 => SlotStatus::Occupied,
+                status => SlotStatus::Unknown(status),
+            },
+            computer: if slot.computer_flag == 1 {
+                Some(match slot.ai_strength {
+                    0 => AiDifficulty::Easy,
+                    1 => AiDifficulty::Normal,
+                    2 => AiDifficulty::Insane,
+                    strength => AiDifficulty::Unknown(strength),
+                })
+            } else {
+                None
+            },
+            team_id: slot.team_id,
+            color: slot.color,
+            race: slot.race.clone(),
+            handicap: slot.handicap_flag,
+            download_percent: slot.download_percent,
+        }
+    }
 }
 
 impl Player {
+    /// The legacy name may be truncated, the protobuf record holds the full battle tag
+    pub(crate) fn merge_reforged_metadata(&mut self, reforged: &ReforgedPlayerMetaData) {
+        if !reforged.battle_tag.is_empty() {
+            self.name = reforged.battle_tag.clone();
+        }
+        if !reforged.clan.is_empty() {
+            self.clan = Some(reforged.clan.clone());
+        }
+        if !reforged.portrait.is_empty() {
+            self.portrait = Some(reforged.portrait.clone());
+        }
+    }
+
+    pub fn is_computer(&self) -> bool {
+        self.computer.is_some()
+    }
+
     pub fn is_observer(&self) -> bool {
         self.team_id == 24
             || self.team_id == 25
@@ -29,5 +115,7 @@ impl Hash for Player {
 impl PartialEq for Player {
     fn eq(&self, other: &Self) -> bool {
         self.id == other.id
+            && self.computer == other.computer
+            && (self.computer.is_none() || self.color == other.color)
     }
 }
@@ -0,0 +1,141 @@
+//! Minimal protobuf wire-format decoding, just enough for the Reforged metadata records
+//! See: https://developers.google.com/protocol-buffers/docs/encoding
+use nom::bytes::complete::take;
+use nom::error::{Error, ErrorKind};
+use nom::number::complete::{le_u32, le_u64, le_u8};
+use nom::IResult;
+
+#[derive(Debug, PartialEq, Clone)]
+pub(crate) enum WireValue<'a> {
+    Varint(u64),
+    Fixed64(u64),
+    Bytes(&'a [u8]),
+    Fixed32(u32),
+}
+
+impl<'a> WireValue<'a> {
+    pub(crate) fn as_u32(&self) -> Option<u32> {
+        match self {
+            WireValue::Varint(v) | WireValue::Fixed64(v) => Some(*v as u32),
+            WireValue::Fixed32(v) => Some(*v),
+            WireValue::Bytes(_) => None,
+        }
+    }
+
+    pub(crate) fn as_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            WireValue::Bytes(b) => Some(b),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn as_string(&self) -> Option<String> {
+        self.as_bytes()
+            .map(|b| String::from_utf8_lossy(b).to_string())
+    }
+}
+
+pub(crate) fn varint(input: &[u8]) -> IResult<&[u8], u64> {
+    let mut rest = input;
+    let mut value: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let (r, byte) = le_u8(rest)?;
+        rest = r;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok((rest, value));
+        }
+    }
+    Err(nom::Err::Error(Error::new(input, ErrorKind::TooLarge)))
+}
+
+pub(crate) fn field(input: &[u8]) -> IResult<&[u8], (u32, WireValue<'_>)> {
+    let (rest, key) = varint(input)?;
+    let number = (key >> 3) as u32;
+    match key & 0x07 {
+        0 => varint(rest).map(|(r, v)| (r, (number, WireValue::Varint(v)))),
+        1 => le_u64(rest).map(|(r, v)| (r, (number, WireValue::Fixed64(v)))),
+        2 => {
+            let (rest, length) = varint(rest)?;
+            let (rest, bytes) = take(length as usize)(rest)?;
+            Ok((rest, (number, WireValue::Bytes(bytes))))
+        }
+        5 => le_u32(rest).map(|(r, v)| (r, (number, WireValue::Fixed32(v)))),
+        _ => Err(nom::Err::Error(Error::new(input, ErrorKind::Switch))),
+    }
+}
+
+/// Decodes a whole message into its (field number, value) pairs
+pub(crate) fn message(input: &[u8]) -> IResult<&[u8], Vec<(u32, WireValue<'_>)>> {
+    let mut rest = input;
+    let mut fields = Vec::new();
+    while !rest.is_empty() {
+        let (r, f) = field(rest)?;
+        fields.push(f);
+        rest = r;
+    }
+    Ok((rest, fields))
+}
+
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+pub(crate) fn write_field(out: &mut Vec<u8>, number: u32, value: &WireValue) {
+    let wire_type = match value {
+        WireValue::Varint(_) => 0,
+        WireValue::Fixed64(_) => 1,
+        WireValue::Bytes(_) => 2,
+        WireValue::Fixed32(_) => 5,
+    };
+    write_varint(out, ((number as u64) << 3) | wire_type);
+    match value {
+        WireValue::Varint(v) => write_varint(out, *v),
+        WireValue::Fixed64(v) => out.extend_from_slice(&v.to_le_bytes()),
+        WireValue::Bytes(b) => {
+            write_varint(out, b.len() as u64);
+            out.extend_from_slice(b);
+        }
+        WireValue::Fixed32(v) => out.extend_from_slice(&v.to_le_bytes()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::protobuf::{message, varint, write_field, write_varint, WireValue};
+
+    #[test]
+    fn decode_varint() {
+        assert_eq!(1, varint(&[0x01]).unwrap().1);
+        assert_eq!(300, varint(&[0xAC, 0x02]).unwrap().1);
+        assert!(varint(&[0xAC]).is_err());
+    }
+
+    #[test]
+    fn decode_message() {
+        let (rest, fields) = message(&[0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i']).unwrap();
+        assert!(rest.is_empty());
+        assert_eq!(
+            vec![(1, WireValue::Varint(150)), (2, WireValue::Bytes(b"hi"))],
+            fields
+        );
+    }
+
+    #[test]
+    fn encode_message() {
+        let mut varint = Vec::new();
+        write_varint(&mut varint, 300);
+        assert_eq!(vec![0xAC, 0x02], varint);
+        let encoded = [0x08, 0x96, 0x01, 0x12, 0x02, b'h', b'i'];
+        let (_, fields) = message(&encoded).unwrap();
+        let mut out = Vec::new();
+        for (number, value) in &fields {
+            write_field(&mut out, *number, value);
+        }
+        assert_eq!(encoded.to_vec(), out);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::blocks::compressedblock::{
+    compressed_data_block, compressed_data_blocks, CompressedDataBlock,
+};
+use crate::blocks::gameblock::{parsed_game_block, GameBlock};
+use crate::error::{failed_at, NomError, ParseStage, W3rsError};
+use crate::event::GameEvent;
+use crate::game::{parse_setup, EventTracker};
+use crate::map::{GameSettings, MapInfo};
+use crate::metadata::replay::{parse_header, GameVersion, ReplayHeader};
+use crate::player::Player;
+use std::collections::VecDeque;
+
+/// Reads the events of a replay without decompressing it entirely:
+/// data blocks are inflated one at a time, as game blocks get parsed
+pub struct GameStream<'a> {
+    pub header: ReplayHeader,
+    pub players: Vec<Player>,
+    pub map: MapInfo,
+    pub settings: GameSettings,
+    version: GameVersion,
+    data: GameData<'a>,
+    tracker: EventTracker,
+    events: VecDeque<GameEvent>,
+    done: bool,
+}
+
+/// The decompressed game data, only keeping what hasn't been parsed yet
+struct GameData<'a> {
+    blocks: Vec<CompressedDataBlock<'a>>,
+    next_block: usize,
+    /// Game data left to inflate, blocks are padded with zeros
+    remaining: usize,
+    /// Inflated game data, parsed up to `position`
+    buffer: Vec<u8>,
+    position: usize,
+    /// Parsed game data dropped from the start of `buffer`
+    dropped: usize,
+}
+
+impl<'a> GameStream<'a> {
+    /// Compressed data blocks are borrowed from `file`
+    pub fn new(file: &'a [u8]) -> Result<GameStream<'a>, W3rsError> {
+        let (rest, file_metadata) =
+            parse_header(file).map_err(failed_at(ParseStage::Header, file))?;
+        let header = ReplayHeader::from(&file_metadata);
+        let version = header.game_version();
+        let (rest, blocks) = compressed_data_blocks(version)(rest)
+            .map_err(failed_at(ParseStage::CompressedDataBlocks, file))?;
+        if blocks.len() < file_metadata.compressed_data_block_count as usize {
+            if let Err(e) = compressed_data_block(rest, version) {
+                return Err(failed_at(ParseStage::CompressedDataBlocks, file)(e));
+            }
+        }
+        let mut data = GameData {
+            blocks,
+            next_block: 0,
+            remaining: header.decompressed_size as usize,
+            buffer: Vec::new(),
+            position: 0,
+            dropped: 0,
+        };
+        // the setup usually fits in the first block, Reforged metadata may not
+        let setup = loop {
+            match parse_setup(&data.buffer, version) {
+                Ok((rest, setup)) => {
+                    data.position = data.buffer.len() - rest.len();
+                    break setup;
+                }
+                Err(e) => {
+                    if !data.inflate_next()? {
+                        return Err(e);
+                    }
+                }
+            }
+        };
+        let (settings, map) = setup.map_info()?;
+        Ok(GameStream {
+            header,
+            players: setup.players(),
+            map,
+            settings,
+            version,
+            data,
+            tracker: EventTracker::new(&setup.slots),
+            events: VecDeque::new(),
+            done: false,
+        })
+    }
+
+    fn next_game_block(&mut self) -> Result<Option<GameBlock>, W3rsError> {
+        let data = &mut self.data;
+        loop {
+            if data.position == data.buffer.len() && !data.inflate_next()? {
+                return Ok(None);
+            }
+            let failure = match parsed_game_block(&data.buffer[data.position..], self.version) {
+                Ok((rest, block)) => {
+                    data.position = data.buffer.len() - rest.len();
+                    return Ok(Some(block));
+                }
+                Err(e) => data.failed_at(e),
+            };
+            // the game block may continue in the next data block
+            if !data.inflate_next()? {
+                return Err(failure);
+            }
+        }
+    }
+}
+
+impl GameData<'_> {
+    /// Appends the next data block to the buffer, returns false if there's none left
+    fn inflate_next(&mut self) -> Result<bool, W3rsError> {
+        let block = match self.blocks.get(self.next_block) {
+            Some(block) if self.remaining > 0 => block,
+            _ => return Ok(false),
+        };
+        let mut inflated = block.inflate().map_err(|source| W3rsError::Deflate {
+            block: self.next_block,
+            source,
+        })?;
+        inflated.truncate(self.remaining);
+        self.remaining -= inflated.len();
+        self.next_block += 1;
+        self.buffer.drain(..self.position);
+        self.dropped += self.position;
+        self.position = 0;
+        self.buffer.extend(inflated);
+        Ok(true)
+    }
+
+    /// Offsets are relative to the whole decompressed game data, like with `Game::from_bytes`
+    fn failed_at(&self, e: NomError) -> W3rsError {
+        match failed_at(ParseStage::GameBlocks, &self.buffer)(e) {
+            W3rsError::Parse {
+                stage,
+                offset,
+                kind,
+            } => W3rsError::Parse {
+                stage,
+                offset: offset + self.dropped,
+                kind,
+            },
+            other => other,
+        }
+    }
+}
+
+impl Iterator for GameStream<'_> {
+    type Item = Result<GameEvent, W3rsError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            match self.next_game_block() {
+                Ok(Some(block)) => {
+                    let mut events = Vec::new();
+                    self.tracker.track(&block, &mut events);
+                    self.events.extend(events);
+                }
+                Ok(None) => self.done = true,
+                Err(failure) => {
+                    self.done = true;
+                    return Some(Err(failure));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::blocks::gameblock::write_game_blocks;
+    use crate::game::tests::decompressed;
+    use crate::game::{write_replay, Game};
+    use crate::stream::GameStream;
+    use crate::tests::{replay_bytes, replays_dir};
+    use std::ffi::OsStr;
+    use std::fs;
+
+    #[test]
+    fn stream_events() {
+        for path in fs::read_dir(replays_dir())
+            .unwrap()
+            .map(|f| f.unwrap().path())
+            .filter(|f| f.extension() == Some(OsStr::new("w3g")))
+        {
+            let bytes = fs::read(&path).unwrap();
+            let game = Game::from_bytes(&bytes).unwrap();
+            let stream = GameStream::new(&bytes).unwrap();
+            assert_eq!(game.header, stream.header);
+            assert_eq!(game.players, stream.players);
+            assert_eq!(game.settings, stream.settings);
+            let events = stream.collect::<Result<Vec<_>, _>>().unwrap();
+            assert_eq!(game.events(), events, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn stream_failure() {
+        let bytes = replay_bytes("reforged2010.w3g");
+        let game = Game::from_bytes(&bytes).unwrap();
+        let (header, mut data) = decompressed(&bytes);
+        data.truncate(header.decompressed_size as usize);
+        // a time slot too short to hold its time increment, far from the first data block
+        let mut tail = Vec::new();
+        write_game_blocks(&mut tail, &game.blocks[game.blocks.len() / 2..]);
+        let offset = data.len() - tail.len();
+        assert!(offset > 8192);
+        data[offset..offset + 3].copy_from_slice(&[0x1F, 0x01, 0x00]);
+        let corrupted = write_replay(&game.header, &data).unwrap();
+        assert_eq!(header, decompressed(&corrupted).0);
+        let expected = Game::from_bytes(&corrupted).unwrap_err();
+        let failure = GameStream::new(&corrupted)
+            .unwrap()
+            .find_map(Result::err)
+            .unwrap();
+        assert_eq!(expected.to_string(), failure.to_string());
+    }
+}
@@ -11,6 +11,11 @@ pub(crate) fn zero_terminated(input: &[u8]) -> IResult<&[u8], &[u8]> {
     terminated(take_while(|b: u8| b != 0), tag([0]))(input)
 }
 
+pub(crate) fn write_zero_terminated(out: &mut Vec<u8>, bytes: &[u8]) {
+    out.extend_from_slice(bytes);
+    out.push(0);
+}
+
 /// See: https://gist.github.com/dengzhp/1185519#file-w3g_format-txt-L435
 pub(crate) fn decode(encoded: &[u8]) -> Vec<u8> {
     let mut decoded_string: Vec<u8> = Vec::new();
@@ -19,7 +24,7 @@ pub(crate) fn decode(encoded: &[u8]) -> Vec<u8> {
     let mut dpos: usize = 0;
 
     while encoded.get(pos).is_some() {
-        if pos % 8 == 0 {
+        if pos.is_multiple_of(8) {
             mask = *(encoded.get(pos).unwrap()) as usize;
         } else {
             if (mask & (0x1 << (pos % 8))) == 0 {
@@ -33,3 +38,29 @@ pub(crate) fn decode(encoded: &[u8]) -> Vec<u8> {
     }
     decoded_string
 }
+
+/// The opposite of `decode`: odd bytes are kept and flagged in the mask, even ones incremented
+pub(crate) fn encode(decoded: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::with_capacity(decoded.len() + decoded.len() / 7 + 1);
+    for chunk in decoded.chunks(7) {
+        let mut mask: u8 = 0x01;
+        let mut bytes = Vec::with_capacity(7);
+        for (i, byte) in chunk.iter().enumerate() {
+            if byte % 2 == 0 {
+                bytes.push(byte + 1);
+            } else {
+                bytes.push(*byte);
+                mask |= 0x1 << (i + 1);
+            }
+        }
+        encoded.push(mask);
+        encoded.extend(bytes);
+    }
+    encoded
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(bytes);
+    hasher.finalize()
+}
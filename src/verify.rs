@@ -0,0 +1,103 @@
+//! Checks the integrity of a replay file without parsing the game itself
+use crate::blocks::compressedblock::compressed_data_block;
+use crate::error::{failed_at, ParseStage, W3rsError};
+use crate::metadata::replay::{parse_header, ReplayHeader};
+use crate::utils::crc32;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChecksumReport {
+    /// The header CRC32 matches its content
+    pub header_valid: bool,
+    /// Number of data blocks announced by the header
+    pub block_count: usize,
+    /// Indexes of the data blocks whose checksum doesn't match, or which can't be read at all
+    pub corrupted_blocks: Vec<usize>,
+}
+
+impl ChecksumReport {
+    pub fn is_valid(&self) -> bool {
+        self.header_valid && self.corrupted_blocks.is_empty()
+    }
+}
+
+/// Computes the header CRC32 and the checksum of every data block.
+/// Fails only if the header itself can't be read
+pub fn verify(file: &[u8]) -> Result<ChecksumReport, W3rsError> {
+    let (mut rest, file_metadata) =
+        parse_header(file).map_err(failed_at(ParseStage::Header, file))?;
+    let header_size = file.len() - rest.len();
+    let mut header = file[..header_size].to_vec();
+    header[header_size - 4..].copy_from_slice(&[0; 4]); // checksum
+    let header_valid = crc32(&header) == file_metadata.replay_metadata.checksum;
+
+    let version = ReplayHeader::from(&file_metadata).game_version();
+    let block_count = file_metadata.compressed_data_block_count as usize;
+    let mut corrupted_blocks = Vec::new();
+    for index in 0..block_count {
+        match compressed_data_block(rest, version) {
+            Ok((next, block)) => {
+                if block.checksum(version) != block.stored_checksum {
+                    corrupted_blocks.push(index);
+                }
+                rest = next;
+            }
+            Err(_) => {
+                corrupted_blocks.extend(index..block_count);
+                break;
+            }
+        }
+    }
+    Ok(ChecksumReport {
+        header_valid,
+        block_count,
+        corrupted_blocks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::tests::{replay_bytes, replays_dir};
+    use crate::verify::verify;
+    use std::ffi::OsStr;
+    use std::fs;
+
+    #[test]
+    fn fixtures_are_valid() {
+        for path in fs::read_dir(replays_dir())
+            .unwrap()
+            .map(|f| f.unwrap().path())
+            .filter(|f| f.extension() == Some(OsStr::new("w3g")))
+        {
+            let report = verify(&fs::read(&path).unwrap()).unwrap();
+            assert!(report.is_valid(), "{:?}: {:?}", path, report);
+        }
+    }
+
+    #[test]
+    fn written_replays_are_valid() {
+        let game = Game::from_bytes(&replay_bytes("reforged1.w3g")).unwrap();
+        assert!(verify(&game.to_bytes().unwrap()).unwrap().is_valid());
+    }
+
+    #[test]
+    fn detect_tampering() {
+        let mut file = replay_bytes("reforged1.w3g");
+        file[0x38] ^= 0xFF; // replay duration
+        let report = verify(&file).unwrap();
+        assert!(!report.header_valid);
+        assert!(report.corrupted_blocks.is_empty());
+
+        let mut file = replay_bytes("reforged1.w3g");
+        let last = file.len() - 10;
+        file[last] ^= 0xFF;
+        let report = verify(&file).unwrap();
+        assert!(report.header_valid);
+        assert_eq!(vec![11], report.corrupted_blocks);
+
+        let file = replay_bytes("reforged1.w3g");
+        let report = verify(&file[..file.len() - 5000]).unwrap();
+        assert_eq!(12, report.block_count);
+        assert_eq!(vec![10, 11], report.corrupted_blocks);
+    }
+}
@@ -0,0 +1,425 @@
+//! Statistics written by custom maps through the W3MMD protocol (DotA-likes, Legion TD, etc.).
+//! Messages are numbered `val:<id>` "mission keys" of `MMD.Dat` sync actions
+use crate::blocks::command::{ParsedAction, W3MMDAction};
+use crate::blocks::gameblock::GameBlock;
+use crate::game::Game;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VarType {
+    Int,
+    Real,
+    String,
+    Unknown(String),
+}
+
+/// Whether players should aim for high or low values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Goal {
+    High,
+    Low,
+    None,
+    Unknown(String),
+}
+
+/// How stats sites should display a variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Suggestion {
+    None,
+    Track,
+    Leaderboard,
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VarDefinition {
+    pub name: String,
+    pub kind: VarType,
+    pub goal: Goal,
+    pub suggestion: Suggestion,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VarValue {
+    Int(i64),
+    Real(f64),
+    String(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PlayerFlag {
+    Winner,
+    Loser,
+    Drawer,
+    Leaver,
+    Practicing,
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventDefinition {
+    pub name: String,
+    pub arguments: Vec<String>,
+    /// Describes the event, `{0}` being replaced by the first argument and so on
+    pub format: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomEvent {
+    pub time: Duration,
+    pub name: String,
+    pub arguments: Vec<String>,
+}
+
+/// The state of W3MMD statistics at the end of the replay.
+/// Player ids (pids) are the map's, not the replay's: see `players` for their names
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct W3mmdReport {
+    /// Minimum and current version of the protocol the map uses
+    pub version: Option<(u32, u32)>,
+    pub players: BTreeMap<u32, String>,
+    pub variables: Vec<VarDefinition>,
+    /// Values of variables, by pid then variable name
+    pub values: BTreeMap<u32, BTreeMap<String, VarValue>>,
+    pub flags: BTreeMap<u32, Vec<PlayerFlag>>,
+    pub event_definitions: Vec<EventDefinition>,
+    pub events: Vec<CustomEvent>,
+    /// Messages that couldn't be understood, custom ones included
+    pub unknown: Vec<String>,
+}
+
+impl W3mmdReport {
+    pub fn player_name(&self, pid: u32) -> Option<&str> {
+        self.players.get(&pid).map(String::as_str)
+    }
+
+    pub fn value(&self, pid: u32, variable: &str) -> Option<&VarValue> {
+        self.values
+            .get(&pid)
+            .and_then(|values| values.get(variable))
+    }
+
+    pub fn has_flag(&self, pid: u32, flag: &PlayerFlag) -> bool {
+        self.flags
+            .get(&pid)
+            .is_some_and(|flags| flags.contains(flag))
+    }
+
+    fn variable(&self, name: &str) -> Option<&VarDefinition> {
+        self.variables.iter().find(|var| var.name == name)
+    }
+
+    fn apply(&mut self, time: Duration, message: &str) {
+        let tokens = tokenize(message);
+        let args: Vec<&str> = tokens.iter().map(String::as_str).collect();
+        let understood = match args.as_slice() {
+            ["init", "version", minimum, current] => match (minimum.parse(), current.parse()) {
+                (Ok(minimum), Ok(current)) => {
+                    self.version = Some((minimum, current));
+                    true
+                }
+                _ => false,
+            },
+            ["init", "pid", pid, name] => pid
+                .parse()
+                .map(|pid| self.players.insert(pid, name.to_string()))
+                .is_ok(),
+            ["DefVarP", name, kind, goal, suggestion] => {
+                self.variables.push(VarDefinition {
+                    name: name.to_string(),
+                    kind: var_type(kind),
+                    goal: goal_of(goal),
+                    suggestion: suggestion_of(suggestion),
+                });
+                true
+            }
+            ["VarP", pid, name, operator, value] => match pid.parse() {
+                Ok(pid) => self.set_value(pid, name, operator, value),
+                Err(_) => false,
+            },
+            ["FlagP", pid, flag] => match pid.parse() {
+                Ok(pid) => {
+                    self.flags.entry(pid).or_default().push(player_flag(flag));
+                    true
+                }
+                Err(_) => false,
+            },
+            ["DefEvent", name, count, rest @ ..] => match count.parse::<usize>() {
+                Ok(count) if rest.len() == count + 1 => {
+                    self.event_definitions.push(EventDefinition {
+                        name: name.to_string(),
+                        arguments: rest[..count].iter().map(|arg| arg.to_string()).collect(),
+                        format: rest[count].to_string(),
+                    });
+                    true
+                }
+                _ => false,
+            },
+            ["Event", name, arguments @ ..] => {
+                self.events.push(CustomEvent {
+                    time,
+                    name: name.to_string(),
+                    arguments: arguments.iter().map(|arg| arg.to_string()).collect(),
+                });
+                true
+            }
+            ["Blank"] => true,
+            _ => false,
+        };
+        if !understood {
+            self.unknown.push(message.to_string());
+        }
+    }
+
+    fn set_value(&mut self, pid: u32, name: &str, operator: &str, value: &str) -> bool {
+        let kind = match self.variable(name) {
+            Some(var) => var.kind.clone(),
+            None => return false,
+        };
+        let current = self.values.entry(pid).or_default();
+        let value = match (kind, operator) {
+            (VarType::String, "=") => VarValue::String(unquote(value)),
+            (VarType::Int, _) => match value.parse::<i64>() {
+                Ok(value) => {
+                    let previous = match current.get(name) {
+                        Some(VarValue::Int(previous)) => *previous,
+                        _ => 0,
+                    };
+                    // the map's script may overflow
+                    let value = match operator {
+                        "=" => Some(value),
+                        "+=" => previous.checked_add(value),
+                        "-=" => previous.checked_sub(value),
+                        _ => None,
+                    };
+                    match value {
+                        Some(value) => VarValue::Int(value),
+                        None => return false,
+                    }
+                }
+                Err(_) => return false,
+            },
+            (VarType::Real, _) => match value.parse::<f64>() {
+                Ok(value) => {
+                    let previous = match current.get(name) {
+                        Some(VarValue::Real(previous)) => *previous,
+                        _ => 0.0,
+                    };
+                    match operator {
+                        "=" => VarValue::Real(value),
+                        "+=" => VarValue::Real(previous + value),
+                        "-=" => VarValue::Real(previous - value),
+                        _ => return false,
+                    }
+                }
+                Err(_) => return false,
+            },
+            _ => return false,
+        };
+        current.insert(name.to_string(), value);
+        true
+    }
+}
+
+/// Splits a message on spaces, `\` escaping spaces, quotes and itself
+fn tokenize(message: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = message.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => token.extend(chars.next()),
+            ' ' => tokens.push(std::mem::take(&mut token)),
+            c => token.push(c),
+        }
+    }
+    tokens.push(token);
+    tokens
+}
+
+/// String values are quoted
+fn unquote(value: &str) -> String {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .to_string()
+}
+
+fn var_type(kind: &str) -> VarType {
+    match kind {
+        "int" => VarType::Int,
+        "real" => VarType::Real,
+        "string" => VarType::String,
+        other => VarType::Unknown(other.to_string()),
+    }
+}
+
+fn goal_of(goal: &str) -> Goal {
+    match goal {
+        "high" => Goal::High,
+        "low" => Goal::Low,
+        "none" => Goal::None,
+        other => Goal::Unknown(other.to_string()),
+    }
+}
+
+fn suggestion_of(suggestion: &str) -> Suggestion {
+    match suggestion {
+        "none" => Suggestion::None,
+        "track" => Suggestion::Track,
+        "leaderboard" => Suggestion::Leaderboard,
+        other => Suggestion::Unknown(other.to_string()),
+    }
+}
+
+fn player_flag(flag: &str) -> PlayerFlag {
+    match flag {
+        "winner" => PlayerFlag::Winner,
+        "loser" => PlayerFlag::Loser,
+        "drawer" => PlayerFlag::Drawer,
+        "leaver" => PlayerFlag::Leaver,
+        "practicing" => PlayerFlag::Practicing,
+        other => PlayerFlag::Unknown(other.to_string()),
+    }
+}
+
+impl Game {
+    /// Replays the W3MMD messages of the map, `None` if it doesn't send any
+    pub fn w3mmd(&self) -> Option<W3mmdReport> {
+        // messages are numbered, and may be sent by several players
+        let mut messages: BTreeMap<u32, (Duration, &str)> = BTreeMap::new();
+        let mut time = Duration::from_millis(0);
+        for block in &self.blocks {
+            if let GameBlock::TimeSlot(ts) = block {
+                time += Duration::from_millis(ts.time_increment as u64);
+                for action in ts.commands.iter().flat_map(|cmd| &cmd.actions) {
+                    if let ParsedAction::W3MMD(W3MMDAction {
+                        mission_key, key, ..
+                    }) = action
+                    {
+                        // "chk:<id>" messages hold checksums
+                        if let Some(Ok(id)) = mission_key.strip_prefix("val:").map(str::parse) {
+                            messages.entry(id).or_insert((time, key.as_str()));
+                        }
+                    }
+                }
+            }
+        }
+        if messages.is_empty() {
+            return None;
+        }
+        let mut report = W3mmdReport::default();
+        for (time, message) in messages.into_values() {
+            report.apply(time, message);
+        }
+        Some(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::tests::replay_bytes;
+    use crate::w3mmd::{tokenize, PlayerFlag, VarType, VarValue, W3mmdReport};
+    use std::time::Duration;
+
+    #[test]
+    fn tokenize_messages() {
+        assert_eq!(
+            vec!["VarP", "6", "hero", "\"Doctrinal Cake\""],
+            tokenize("VarP 6 hero = \"Doctrinal\\ Cake\"")
+                .into_iter()
+                .filter(|t| t != "=")
+                .collect::<Vec<String>>()
+        );
+        assert_eq!(vec!["a\\b", "c"], tokenize("a\\\\b c"));
+    }
+
+    #[test]
+    fn replay_messages() {
+        let mut report = W3mmdReport::default();
+        for message in [
+            "init version 0 1",
+            "init pid 0 Grubby",
+            "DefVarP kills int high leaderboard",
+            "DefVarP ratio real none track",
+            "VarP 0 kills = 3",
+            "VarP 0 kills += 4",
+            "VarP 0 kills -= 2",
+            "VarP 0 ratio += 1.5",
+            "VarP 0 unknown = 1",
+            "FlagP 0 winner",
+            "FlagP 1 leaver",
+            "Custom something",
+        ] {
+            report.apply(Duration::from_secs(1), message);
+        }
+        assert_eq!(Some((0, 1)), report.version);
+        assert_eq!(Some("Grubby"), report.player_name(0));
+        assert_eq!(Some(&VarValue::Int(5)), report.value(0, "kills"));
+        assert_eq!(Some(&VarValue::Real(1.5)), report.value(0, "ratio"));
+        assert!(report.has_flag(0, &PlayerFlag::Winner));
+        assert!(report.has_flag(1, &PlayerFlag::Leaver));
+        assert!(!report.has_flag(1, &PlayerFlag::Winner));
+        assert_eq!(
+            vec!["VarP 0 unknown = 1", "Custom something"],
+            report.unknown
+        );
+    }
+
+    #[test]
+    fn overflowing_values() {
+        let mut report = W3mmdReport::default();
+        for message in [
+            "DefVarP kills int high leaderboard",
+            "VarP 0 kills = 9223372036854775807",
+            "VarP 0 kills += 1",
+            "VarP 1 kills = -9223372036854775807",
+            "VarP 1 kills -= 2",
+        ] {
+            report.apply(Duration::from_secs(1), message);
+        }
+        assert_eq!(Some(&VarValue::Int(i64::MAX)), report.value(0, "kills"));
+        assert_eq!(Some(&VarValue::Int(-i64::MAX)), report.value(1, "kills"));
+        assert_eq!(
+            vec!["VarP 0 kills += 1", "VarP 1 kills -= 2"],
+            report.unknown
+        );
+    }
+
+    #[test]
+    fn custom_map_report() {
+        let game = Game::from_bytes(&replay_bytes("reforged_metadata_ghostplayer.w3g")).unwrap();
+        let report = game.w3mmd().unwrap();
+        assert!(report.unknown.is_empty());
+        assert_eq!(Some((1, 1)), report.version);
+        assert_eq!(10, report.players.len());
+        assert_eq!(Some("Banjo#12494"), report.player_name(2));
+        assert_eq!(7, report.variables.len());
+        assert_eq!(VarType::String, report.variables[5].kind);
+        assert_eq!(Some(&VarValue::Int(3)), report.value(4, "goals"));
+        assert_eq!(Some(&VarValue::Int(3)), report.value(10, "goals"));
+        assert_eq!(
+            Some(&VarValue::String("Sir Real Punts".to_string())),
+            report.value(2, "hero")
+        );
+        assert_eq!(10, report.event_definitions.len());
+        let goal = &report.event_definitions[0];
+        assert_eq!(vec!["player", "team"], goal.arguments);
+        assert_eq!("{0} {1}", goal.format);
+        let leaves: Vec<&str> = report
+            .events
+            .iter()
+            .filter(|e| e.name == "leave")
+            .map(|e| e.arguments[0].as_str())
+            .collect();
+        assert_eq!(vec!["Enclop#21668", "Ziggs#11403"], leaves);
+        assert_eq!("observe", report.events[0].name);
+        assert_eq!(vec!["Ziggs#11403", "true"], report.events[0].arguments);
+
+        let melee = Game::from_bytes(&replay_bytes("reforged2010.w3g")).unwrap();
+        assert_eq!(None, melee.w3mmd());
+    }
+}
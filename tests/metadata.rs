@@ -0,0 +1,46 @@
+use std::fs;
+use std::path::PathBuf;
+use w3rs::game::{Game, GameContext};
+use w3rs::metadata::{GameFlags, GamePosData, Language, MapSize, MapType, SelectMode};
+
+fn replay(name: &str) -> Game {
+    let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    path.push("replays");
+    path.push(name);
+    Game::from_bytes(&fs::read(path).unwrap()).unwrap()
+}
+
+#[test]
+fn filter_on_game_flags() {
+    let game = replay("reforged_hunter2_privatestring.w3g");
+    match game.flags {
+        GameFlags {
+            context: GameContext::Custom,
+            private: true,
+            map:
+                MapType {
+                    melee: true,
+                    size: Some(MapSize::Small),
+                    ..
+                },
+            ..
+        } => {}
+        flags => panic!("unexpected flags {:?}", flags),
+    }
+    let ladder = replay("reforged1.w3g");
+    assert!(matches!(ladder.language, Language::Unspecified));
+}
+
+#[test]
+fn filter_on_select_mode() {
+    let game = replay("reforged_metadata_ghostplayer.w3g");
+    let GamePosData { select_mode, .. } = game.pos;
+    let fixed_teams = match select_mode {
+        SelectMode::FixedTeams | SelectMode::FixedTeamsAndRaces => true,
+        SelectMode::AutomatedMatchmaking
+        | SelectMode::TeamAndRaceSelectable
+        | SelectMode::RandomRaces
+        | SelectMode::Unknown(_) => false,
+    };
+    assert!(fixed_teams);
+}